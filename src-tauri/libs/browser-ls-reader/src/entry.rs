@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::LOG_ID;
+
+/// One `log*` value of tenhou.net's localStorage.
+///
+/// The web client stores a JSON object per game; older clients stored a query string
+/// such as `file=<id>&tw=<seat>`. Both are read, and fields that are missing stay empty.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LocalLogEntry {
+    pub id: String,
+    /// 按座位顺序的玩家名
+    pub players: Vec<String>,
+    /// 本地玩家的座位 (`tw`), 观战的牌谱没有
+    pub seat: Option<u8>,
+    /// `GO` 的 type, 即规则
+    pub rule: Option<u32>,
+    /// 开始时间, unix 秒
+    pub timestamp: Option<i64>,
+}
+
+impl LocalLogEntry {
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut fields = HashMap::new();
+        let mut players = vec![];
+        match serde_json::from_str::<Value>(value.trim()) {
+            Ok(Value::Object(map)) => {
+                for (key, v) in map {
+                    match v {
+                        Value::String(s) => {
+                            fields.insert(key, s);
+                        }
+                        Value::Number(n) => {
+                            fields.insert(key, n.to_string());
+                        }
+                        Value::Array(names) if key == "name" => {
+                            players = names
+                                .iter()
+                                .map(|n| n.as_str().unwrap_or_default().to_string())
+                                .collect();
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {
+                for (key, v) in value.split('&').filter_map(|pair| pair.split_once('=')) {
+                    let v = urlencoding::decode(v).map_or(v.to_string(), |v| v.to_string());
+                    fields.insert(key.to_string(), v);
+                }
+            }
+        }
+        if players.is_empty() {
+            players = (0..4)
+                .map_while(|i| fields.get(&format!("un{}", i)).cloned())
+                .collect();
+        }
+
+        let id = ["log", "file", "id"]
+            .iter()
+            .filter_map(|key| fields.get(*key).map(|s| s.as_str()))
+            .chain(std::iter::once(value))
+            .find_map(|s| LOG_ID.find(s))?
+            .as_str()
+            .to_string();
+        Some(LocalLogEntry {
+            id,
+            players,
+            seat: number::<u8>(&fields, "tw").filter(|&s| s < 4),
+            rule: number(&fields, "type"),
+            timestamp: number(&fields, "starttime"),
+        })
+    }
+
+    /// Name of the local player, if the entry has the players.
+    pub fn player(&self) -> Option<&str> {
+        self.players.get(self.seat? as usize).map(|s| s.as_str())
+    }
+
+    /// Games watched rather than played are stored with the players but without a seat.
+    /// Entries read from a damaged database only have the id and are never considered spectated.
+    pub fn is_spectated(&self) -> bool {
+        self.seat.is_none() && !self.players.is_empty()
+    }
+}
+
+fn number<T: FromStr>(fields: &HashMap<String, String>, key: &str) -> Option<T> {
+    fields.get(key).and_then(|s| s.parse().ok())
+}
@@ -1,11 +1,69 @@
-use std::path::PathBuf;
+pub mod entry;
+pub mod profile;
 
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use lazy_static::lazy_static;
+use regex::Regex;
 use rusty_leveldb::{LdbIterator, Options, DB};
+use serde::Serialize;
 use sqlite::State;
+use tenhou_parser::LOG_ID_PATTERN;
+
+pub use crate::entry::LocalLogEntry;
+pub use crate::profile::{discover, BrowserProfile};
+
+/// A copy of a leveldb folder in the temp dir, deleted on drop.
+struct TempCopy {
+    path: PathBuf,
+}
+
+impl TempCopy {
+    /// Copies the files of `src` except `LOCK`, which a running browser holds.
+    fn new(src: &Path) -> std::io::Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "browser-ls-reader-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let copy = TempCopy { path };
+        std::fs::create_dir_all(&copy.path)?;
+        for entry in src.read_dir()? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() && entry.file_name() != "LOCK" {
+                std::fs::copy(entry.path(), copy.path.join(entry.file_name()))?;
+            }
+        }
+        Ok(copy)
+    }
+}
+
+impl Drop for TempCopy {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Reads the tenhou.net logs from a Chromium localStorage leveldb.
+///
+/// The database is copied to the temp dir first, so the browser's own files are never
+/// written to and a running browser holding the lock does not get in the way.
+/// If the copy cannot be made, e.g. a file is locked, or the copy cannot be opened, the raw
+/// `.ldb` / `.log` files are scanned for log ids instead.
+pub fn read_leveldb(path: &Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    TempCopy::new(path)
+        .map_err(Into::into)
+        .and_then(|copy| read_leveldb_copy(&copy.path))
+        .or_else(|_| scan_leveldb_files(path))
+}
 
-pub fn read_leveldb(path: &PathBuf) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-    let mut options = Options::default();
-    options.create_if_missing = false;
+fn read_leveldb_copy(path: &Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let options = Options {
+        create_if_missing: false,
+        ..Default::default()
+    };
     let mut db = DB::open(path, options)?;
     let mut iter = db.new_iter()?;
     iter.seek_to_first();
@@ -14,12 +72,9 @@ pub fn read_leveldb(path: &PathBuf) -> Result<Vec<String>, Box<dyn std::error::E
         let mut key = vec![];
         let mut value = vec![];
         iter.current(&mut key, &mut value);
-        if let (Ok(key), Ok(value)) = (
-            String::from_utf8(key.clone()),
-            String::from_utf8(value.clone()),
-        ) {
+        if let (Ok(key), Some(value)) = (String::from_utf8(key.clone()), decode_value(&value)) {
             if key.starts_with("_https://tenhou.net\0\u{1}log") {
-                logs.push(String::from(&value[1..]));
+                logs.push(value);
             }
         }
         iter.next();
@@ -27,6 +82,40 @@ pub fn read_leveldb(path: &PathBuf) -> Result<Vec<String>, Box<dyn std::error::E
     Ok(logs)
 }
 
+/// Chromium prefixes localStorage values with their encoding:
+/// `0` for UTF-16LE (anything with non Latin-1 characters, e.g. Japanese names) and `1` for Latin-1.
+fn decode_value(value: &[u8]) -> Option<String> {
+    match value.split_first()? {
+        (0, rest) => String::from_utf16(
+            &rest
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .collect::<Vec<_>>(),
+        )
+        .ok(),
+        (1, rest) => Some(rest.iter().map(|&b| b as char).collect()),
+        _ => None,
+    }
+}
+
+/// Last resort for a damaged database: the log ids found anywhere in its `.ldb` and `.log` files.
+/// Values in compressed blocks are missed.
+fn scan_leveldb_files(path: &Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut logs = vec![];
+    for entry in path.read_dir()? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "ldb" || ext == "log") {
+            let content = String::from_utf8_lossy(&std::fs::read(&path)?).to_string();
+            logs.extend(LOG_ID.find_iter(&content).map(|m| m.as_str().to_string()));
+        }
+    }
+    Ok(logs)
+}
+
+lazy_static! {
+    static ref LOG_ID: Regex = Regex::new(LOG_ID_PATTERN).unwrap();
+}
+
 pub fn read_sqlite(path: &PathBuf) -> Result<Vec<String>, Box<dyn std::error::Error>> {
     let conn = sqlite::open(path)?;
     let mut stmt = conn.prepare("SELECT * FROM data;")?;
@@ -41,31 +130,38 @@ pub fn read_sqlite(path: &PathBuf) -> Result<Vec<String>, Box<dyn std::error::Er
     Ok(logs)
 }
 
-pub fn read_all() -> Result<Vec<String>, Box<dyn std::error::Error>> {
-    let mut logs = vec![];
-    let app_data_path = PathBuf::from(std::env::var("APPDATA").unwrap());
-    let edge_path = app_data_path.join(r#"Microsoft\Edge\User Data\Default\Local Storage\leveldb"#);
-    logs.append(&mut read_leveldb(&edge_path).unwrap_or(vec![]));
-    let chrome_path =
-        app_data_path.join(r#"Google\Chrome\User Data\Default\Local Storage\leveldb"#);
-    logs.append(&mut read_leveldb(&chrome_path).unwrap_or(vec![]));
-    let firefox_path = glob::glob(
-        &app_data_path
-            .join(r#"Mozilla\Firefox\Profiles\*\storage\default\https+++tenhou.net\ls\data.sqlite"#)
-            .to_str()
-            .unwrap(),
-    )?;
-    for path in firefox_path {
-        if let Ok(path) = path {
-            logs.append(&mut read_sqlite(&path).unwrap_or(vec![]));
+/// A log found in a browser, with the profile it came from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FoundLog {
+    pub id: String,
+    pub profile: BrowserProfile,
+    pub entry: LocalLogEntry,
+}
+
+/// Reads the logs of every given browser profile.
+/// Spectated games are left out, while entries with only an id are kept with `seat: None`;
+/// profiles that cannot be read are skipped.
+pub fn read_profiles(profiles: &[BrowserProfile]) -> Vec<FoundLog> {
+    let mut found = vec![];
+    for profile in profiles {
+        let values = profile.read().unwrap_or_default();
+        let entries = values.iter().filter_map(|value| LocalLogEntry::parse(value));
+        for entry in entries.filter(|entry| !entry.is_spectated()) {
+            found.push(FoundLog {
+                id: entry.id.clone(),
+                profile: profile.clone(),
+                entry,
+            });
         }
     }
-    let reg = regex::Regex::new(r#"\d{10}gm-\d{4}-\d{4}-[0-9a-f]{8}"#).unwrap();
-    let logs = reg
-        .find_iter(&logs.join(" "))
-        .map(|m| m.as_str().to_string())
-        .collect::<Vec<String>>();
-    Ok(logs)
+    found
+}
+
+pub fn read_all() -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    Ok(read_profiles(&discover())
+        .into_iter()
+        .map(|log| log.id)
+        .collect())
 }
 
 #[cfg(test)]
@@ -92,4 +188,130 @@ mod tests {
     fn all_test() {
         println!("{:?}", read_all());
     }
+
+    #[test]
+    fn local_entries() {
+        let entry = LocalLogEntry::parse(
+            r#"{"log":"2024072317gm-0089-0000-87c78500","name":["A","B","C","D"],"tw":1,"type":169,"starttime":1721721600}"#,
+        )
+        .unwrap();
+        assert_eq!(entry.id, "2024072317gm-0089-0000-87c78500");
+        assert_eq!(entry.player(), Some("B"));
+        assert_eq!((entry.rule, entry.timestamp), (Some(169), Some(1721721600)));
+        assert!(!entry.is_spectated());
+
+        let watched =
+            LocalLogEntry::parse(r#"{"log":"2024072317gm-0089-0000-87c78500","name":["A","B","C","D"]}"#)
+                .unwrap();
+        assert_eq!((watched.seat, watched.player()), (None, None));
+        assert!(watched.is_spectated());
+
+        let hex_lobby = LocalLogEntry::parse("file=2024072300gm-00a9-0000-4c2fbc41").unwrap();
+        assert_eq!(hex_lobby.id, "2024072300gm-00a9-0000-4c2fbc41");
+        assert!(!hex_lobby.is_spectated());
+
+        let entry = LocalLogEntry::parse("file=2024072217gm-0089-0000-d5b489ec&un0=%E3%81%82&tw=0").unwrap();
+        assert_eq!((entry.player(), entry.seat), (Some("あ"), Some(0)));
+        assert!(LocalLogEntry::parse("{}").is_none());
+
+        let utf16 = "{\"name\":[\"あ\"]}".encode_utf16().flat_map(|c| c.to_le_bytes());
+        let value = std::iter::once(0).chain(utf16).collect::<Vec<u8>>();
+        assert_eq!(decode_value(&value).unwrap(), "{\"name\":[\"あ\"]}");
+        assert_eq!(decode_value(b"\x01abc").unwrap(), "abc");
+    }
+
+    #[test]
+    fn locked_and_damaged_leveldb() {
+        let dir = std::env::temp_dir().join("browser-ls-reader-locked-leveldb");
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut db = DB::open(&dir, Options::default()).unwrap();
+        db.put(b"_https://tenhou.net\0\x01log0", b"\x012024072317gm-0089-0000-87c78500")
+            .unwrap();
+        db.flush().unwrap();
+        // Still open, as in a running browser.
+        assert_eq!(read_leveldb(&dir).unwrap(), ["2024072317gm-0089-0000-87c78500"]);
+        drop(db);
+
+        std::fs::remove_file(dir.join("CURRENT")).unwrap();
+        let ids = read_leveldb(&dir).unwrap();
+        assert!(ids.contains(&"2024072317gm-0089-0000-87c78500".to_string()));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn discover_linux_profiles() {
+        use crate::profile::{discover_in, Browser, Dirs, Os};
+
+        let home = std::env::temp_dir().join("browser-ls-reader-linux-home");
+        let _ = std::fs::remove_dir_all(&home);
+        let put_log = |dir: &std::path::Path, id: &str| {
+            std::fs::create_dir_all(dir).unwrap();
+            let mut db = DB::open(dir, Options::default()).unwrap();
+            db.put(b"_https://tenhou.net\0\x01log0", format!("\x01{}", id).as_bytes())
+                .unwrap();
+            db.flush().unwrap();
+        };
+        let chrome = home.join(".config/google-chrome");
+        put_log(
+            &chrome.join("Profile 10/Local Storage/leveldb"),
+            "2024072417gm-0089-0000-00000010",
+        );
+        put_log(
+            &chrome.join("Default/Local Storage/leveldb"),
+            "2024072317gm-0089-0000-87c78500",
+        );
+        put_log(
+            &chrome.join("Profile 2/Local Storage/leveldb"),
+            "2024072417gm-0089-0000-00000002",
+        );
+        std::fs::write(
+            chrome.join("Local State"),
+            r#"{"profile":{"info_cache":{"Default":{"name":"Person 1"},"Profile 2":{"name":"Tenhou"}}}}"#,
+        )
+        .unwrap();
+        put_log(
+            &home.join(".config/opera/Local Storage/leveldb"),
+            "2024072517gm-0089-0000-00000000",
+        );
+        let firefox = home.join(".var/app/org.mozilla.firefox/.mozilla/firefox/abcd.default-release");
+        let storage = firefox.join("storage/default/https+++tenhou.net/ls");
+        std::fs::create_dir_all(&storage).unwrap();
+        let conn = sqlite::open(storage.join("data.sqlite")).unwrap();
+        conn.execute(
+            "CREATE TABLE data (key TEXT, value TEXT);
+             INSERT INTO data VALUES ('log0', 'file=2024072217gm-0089-0000-d5b489ec&tw=2');
+             INSERT INTO data VALUES ('lastname', 'A');",
+        )
+        .unwrap();
+        drop(conn);
+
+        let dirs = Dirs {
+            home: Some(home.clone()),
+            ..Default::default()
+        };
+        let profiles = discover_in(Os::Linux, &dirs);
+        let names = profiles
+            .iter()
+            .map(|p| (p.browser, p.name.as_str(), p.display_name.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                (Browser::Chrome, "Default", Some("Person 1")),
+                (Browser::Chrome, "Profile 2", Some("Tenhou")),
+                (Browser::Chrome, "Profile 10", None),
+                (Browser::Opera, "opera", None),
+                (Browser::Firefox, "abcd.default-release", None),
+            ]
+        );
+        let found = read_profiles(&profiles);
+        assert_eq!(found.len(), 5);
+        assert_eq!(found[0].id, "2024072317gm-0089-0000-87c78500");
+        assert_eq!(found[1].id, "2024072417gm-0089-0000-00000002");
+        assert_eq!(found[1].profile.display_name.as_deref(), Some("Tenhou"));
+        assert_eq!(found[4].id, "2024072217gm-0089-0000-d5b489ec");
+        assert_eq!(found[4].entry.seat, Some(2));
+        assert!(discover_in(Os::Windows, &dirs).is_empty());
+        std::fs::remove_dir_all(&home).unwrap();
+    }
 }
@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::{read_leveldb, read_sqlite};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Browser {
+    Chrome,
+    Chromium,
+    Edge,
+    Brave,
+    Vivaldi,
+    Opera,
+    Firefox,
+}
+
+impl Display for Browser {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Browser::Chrome => "Google Chrome",
+            Browser::Chromium => "Chromium",
+            Browser::Edge => "Microsoft Edge",
+            Browser::Brave => "Brave",
+            Browser::Vivaldi => "Vivaldi",
+            Browser::Opera => "Opera",
+            Browser::Firefox => "Mozilla Firefox",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Os {
+    Windows,
+    MacOs,
+    Linux,
+}
+
+impl Os {
+    pub fn current() -> Self {
+        if cfg!(target_os = "windows") {
+            Os::Windows
+        } else if cfg!(target_os = "macos") {
+            Os::MacOs
+        } else {
+            Os::Linux
+        }
+    }
+}
+
+/// The base folders browsers keep their data in. Missing ones are simply not searched.
+#[derive(Debug, Clone, Default)]
+pub struct Dirs {
+    pub home: Option<PathBuf>,
+    /// `%APPDATA%`, Windows only
+    pub app_data: Option<PathBuf>,
+    /// `%LOCALAPPDATA%`, Windows only
+    pub local_app_data: Option<PathBuf>,
+}
+
+impl Dirs {
+    pub fn from_env() -> Self {
+        let var = |name| std::env::var_os(name).map(PathBuf::from);
+        Dirs {
+            home: var("HOME").or_else(|| var("USERPROFILE")),
+            app_data: var("APPDATA"),
+            local_app_data: var("LOCALAPPDATA"),
+        }
+    }
+}
+
+/// The localStorage of one browser profile.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BrowserProfile {
+    pub browser: Browser,
+    /// 配置文件夹名, 例如 `Default`, `Profile 1` 或 Firefox 的 `xxxxxxxx.default-release`
+    pub name: String,
+    /// 浏览器中显示的配置名, 取自 Chromium 系浏览器的 `Local State`
+    pub display_name: Option<String>,
+    /// localStorage 的 leveldb 文件夹或 sqlite 文件
+    pub storage: PathBuf,
+}
+
+impl BrowserProfile {
+    /// The raw localStorage values of tenhou.net that hold logs.
+    pub fn read(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        match self.browser {
+            Browser::Firefox => read_sqlite(&self.storage),
+            _ => read_leveldb(&self.storage),
+        }
+    }
+}
+
+/// Folders holding the profiles of one browser, e.g. `~/.config/google-chrome`.
+fn user_data_dirs(os: Os, dirs: &Dirs) -> Vec<(Browser, PathBuf)> {
+    let mut roots = vec![];
+    let mut add = |base: &Option<PathBuf>, browser: Browser, rel: &str| {
+        if let Some(base) = base {
+            roots.push((browser, base.join(rel)));
+        }
+    };
+    match os {
+        Os::Windows => {
+            add(&dirs.local_app_data, Browser::Chrome, r"Google\Chrome\User Data");
+            add(&dirs.local_app_data, Browser::Chromium, r"Chromium\User Data");
+            add(&dirs.local_app_data, Browser::Edge, r"Microsoft\Edge\User Data");
+            add(&dirs.local_app_data, Browser::Brave, r"BraveSoftware\Brave-Browser\User Data");
+            add(&dirs.local_app_data, Browser::Vivaldi, r"Vivaldi\User Data");
+            add(&dirs.app_data, Browser::Opera, r"Opera Software\Opera Stable");
+            add(&dirs.app_data, Browser::Opera, r"Opera Software\Opera GX Stable");
+            // Older versions of this app looked in the roaming folder.
+            add(&dirs.app_data, Browser::Chrome, r"Google\Chrome\User Data");
+            add(&dirs.app_data, Browser::Edge, r"Microsoft\Edge\User Data");
+            add(&dirs.app_data, Browser::Firefox, r"Mozilla\Firefox\Profiles");
+        }
+        Os::MacOs => {
+            let support = dirs.home.as_ref().map(|h| h.join("Library/Application Support"));
+            add(&support, Browser::Chrome, "Google/Chrome");
+            add(&support, Browser::Chromium, "Chromium");
+            add(&support, Browser::Edge, "Microsoft Edge");
+            add(&support, Browser::Brave, "BraveSoftware/Brave-Browser");
+            add(&support, Browser::Vivaldi, "Vivaldi");
+            add(&support, Browser::Opera, "com.operasoftware.Opera");
+            add(&support, Browser::Opera, "com.operasoftware.OperaGX");
+            add(&support, Browser::Firefox, "Firefox/Profiles");
+        }
+        Os::Linux => {
+            let home = &dirs.home;
+            add(home, Browser::Chrome, ".config/google-chrome");
+            add(home, Browser::Chromium, ".config/chromium");
+            add(home, Browser::Edge, ".config/microsoft-edge");
+            add(home, Browser::Brave, ".config/BraveSoftware/Brave-Browser");
+            add(home, Browser::Vivaldi, ".config/vivaldi");
+            add(home, Browser::Opera, ".config/opera");
+            add(home, Browser::Firefox, ".mozilla/firefox");
+            add(home, Browser::Chromium, "snap/chromium/common/chromium");
+            add(home, Browser::Firefox, "snap/firefox/common/.mozilla/firefox");
+            add(home, Browser::Chrome, ".var/app/com.google.Chrome/config/google-chrome");
+            add(home, Browser::Chromium, ".var/app/org.chromium.Chromium/config/chromium");
+            add(home, Browser::Edge, ".var/app/com.microsoft.Edge/config/microsoft-edge");
+            add(home, Browser::Brave, ".var/app/com.brave.Browser/config/BraveSoftware/Brave-Browser");
+            add(home, Browser::Vivaldi, ".var/app/com.vivaldi.Vivaldi/config/vivaldi");
+            add(home, Browser::Brave, "snap/brave/current/.config/BraveSoftware/Brave-Browser");
+            add(home, Browser::Opera, "snap/opera/current/.config/opera");
+            add(home, Browser::Firefox, ".var/app/org.mozilla.firefox/.mozilla/firefox");
+        }
+    }
+    roots
+}
+
+/// Profile folder name to the name shown in the browser, from `<root>/Local State`.
+fn profile_names(root: &Path) -> HashMap<String, String> {
+    let Ok(content) = std::fs::read_to_string(root.join("Local State")) else {
+        return HashMap::new();
+    };
+    let Ok(state) = serde_json::from_str::<serde_json::Value>(&content) else {
+        return HashMap::new();
+    };
+    state["profile"]["info_cache"]
+        .as_object()
+        .map(|cache| {
+            cache
+                .iter()
+                .filter_map(|(dir, info)| Some((dir.clone(), info["name"].as_str()?.to_string())))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// `Default` and `Profile 1..N` of a Chromium based browser. Opera keeps its only
+/// profile directly in the root, which is then reported with the root's name.
+fn chromium_profiles(browser: Browser, root: &Path) -> Vec<BrowserProfile> {
+    let storage = |dir: &Path| dir.join("Local Storage").join("leveldb");
+    let names = profile_names(root);
+    let mut profiles = vec![];
+    if storage(root).is_dir() {
+        profiles.push(BrowserProfile {
+            browser,
+            name: root.file_name().unwrap_or_default().to_string_lossy().to_string(),
+            display_name: None,
+            storage: storage(root),
+        });
+    }
+    let Ok(entries) = root.read_dir() else {
+        return profiles;
+    };
+    let mut dirs = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| {
+            name == "Default" || name.starts_with("Profile ") || names.contains_key(name)
+        })
+        .filter(|name| storage(&root.join(name)).is_dir())
+        .collect::<Vec<_>>();
+    // Default first, then Profile 1, Profile 2, ..., Profile 10 in numeric order.
+    dirs.sort_by_key(|name| {
+        let n = name.strip_prefix("Profile ").and_then(|n| n.parse::<u32>().ok());
+        (name != "Default", n.is_none(), n, name.clone())
+    });
+    profiles.extend(dirs.into_iter().map(|name| BrowserProfile {
+        browser,
+        display_name: names.get(&name).cloned(),
+        storage: storage(&root.join(&name)),
+        name,
+    }));
+    profiles
+}
+
+fn firefox_profiles(root: &Path) -> Vec<BrowserProfile> {
+    let Ok(entries) = root.read_dir() else {
+        return vec![];
+    };
+    let mut profiles = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let storage = entry
+                .path()
+                .join("storage/default/https+++tenhou.net/ls/data.sqlite");
+            storage.is_file().then(|| BrowserProfile {
+                browser: Browser::Firefox,
+                name: entry.file_name().to_string_lossy().to_string(),
+                display_name: None,
+                storage,
+            })
+        })
+        .collect::<Vec<_>>();
+    profiles.sort_by(|a, b| a.name.cmp(&b.name));
+    profiles
+}
+
+/// Every browser profile with tenhou.net localStorage on this machine.
+pub fn discover() -> Vec<BrowserProfile> {
+    discover_in(Os::current(), &Dirs::from_env())
+}
+
+pub fn discover_in(os: Os, dirs: &Dirs) -> Vec<BrowserProfile> {
+    let mut profiles: Vec<BrowserProfile> = vec![];
+    for (browser, root) in user_data_dirs(os, dirs) {
+        let found = match browser {
+            Browser::Firefox => firefox_profiles(&root),
+            _ => chromium_profiles(browser, &root),
+        };
+        for profile in found {
+            if !profiles.iter().any(|p| p.storage == profile.storage) {
+                profiles.push(profile);
+            }
+        }
+    }
+    profiles
+}
@@ -0,0 +1,96 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields};
+
+/// Derives field-wise `AddAssign`, `Add`, `SubAssign`, `Sub` and `Sum` for a struct
+/// whose fields all implement `AddAssign<&T>` and `SubAssign<&T>`.
+///
+/// Used to merge counters computed separately, e.g. one per log file.
+#[proc_macro_derive(CounterOps)]
+pub fn derive_counter_ops(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => fields
+                .named
+                .iter()
+                .map(|f| f.ident.clone().unwrap())
+                .collect::<Vec<_>>(),
+            _ => {
+                return syn::Error::new_spanned(name, "CounterOps requires named fields")
+                    .to_compile_error()
+                    .into()
+            }
+        },
+        _ => {
+            return syn::Error::new_spanned(name, "CounterOps can only be derived for structs")
+                .to_compile_error()
+                .into()
+        }
+    };
+
+    let expanded = quote! {
+        impl ::std::ops::AddAssign<&#name> for #name {
+            fn add_assign(&mut self, rhs: &#name) {
+                #( self.#fields += &rhs.#fields; )*
+            }
+        }
+
+        impl ::std::ops::AddAssign for #name {
+            fn add_assign(&mut self, rhs: #name) {
+                *self += &rhs;
+            }
+        }
+
+        impl ::std::ops::Add for #name {
+            type Output = #name;
+
+            fn add(mut self, rhs: #name) -> Self::Output {
+                self += &rhs;
+                self
+            }
+        }
+
+        impl ::std::ops::SubAssign<&#name> for #name {
+            fn sub_assign(&mut self, rhs: &#name) {
+                #( self.#fields -= &rhs.#fields; )*
+            }
+        }
+
+        impl ::std::ops::SubAssign for #name {
+            fn sub_assign(&mut self, rhs: #name) {
+                *self -= &rhs;
+            }
+        }
+
+        impl ::std::ops::Sub for #name {
+            type Output = #name;
+
+            fn sub(mut self, rhs: #name) -> Self::Output {
+                self -= &rhs;
+                self
+            }
+        }
+
+        impl ::std::iter::Sum for #name {
+            fn sum<I: Iterator<Item = #name>>(iter: I) -> Self {
+                iter.fold(::std::default::Default::default(), |mut acc, x| {
+                    acc += &x;
+                    acc
+                })
+            }
+        }
+
+        impl<'a> ::std::iter::Sum<&'a #name> for #name {
+            fn sum<I: Iterator<Item = &'a #name>>(iter: I) -> Self {
+                iter.fold(::std::default::Default::default(), |mut acc, x| {
+                    acc += x;
+                    acc
+                })
+            }
+        }
+    };
+    expanded.into()
+}
@@ -0,0 +1,42 @@
+//! Compares serial and parallel analysis of a logs folder.
+//!
+//! `TENHOU_LOGS_DIR=/path/to/logs TENHOU_PLAYER=name cargo bench -p maj-analyser`
+
+use std::path::PathBuf;
+use std::time::Instant;
+
+use maj_analyser::game::Game;
+use maj_analyser::parallel::analyse_files;
+use tenhou_parser::event_emitter::try_parse_file;
+
+fn main() {
+    let dir = PathBuf::from(std::env::var("TENHOU_LOGS_DIR").unwrap_or("logs".to_string()));
+    let ids = vec![std::env::var("TENHOU_PLAYER").unwrap_or_default()];
+    let paths = match dir.read_dir() {
+        Ok(rd) => rd
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.is_file())
+            .collect::<Vec<_>>(),
+        Err(e) => {
+            eprintln!("Cannot read {}: {}", dir.display(), e);
+            return;
+        }
+    };
+    println!("{} files in {}", paths.len(), dir.display());
+
+    let start = Instant::now();
+    let mut game = Game::new(&ids);
+    for path in paths.iter() {
+        if let Ok(events) = try_parse_file(path) {
+            events.into_iter().for_each(|event| game.on_event(event));
+        }
+    }
+    let serial = game.into_counters();
+    println!("serial:   {:?}", start.elapsed());
+
+    let start = Instant::now();
+    let parallel = analyse_files(&paths, &ids);
+    println!("parallel: {:?}", start.elapsed());
+
+    assert_eq!(serial[&ids[0]].matches, parallel[&ids[0]].matches);
+}
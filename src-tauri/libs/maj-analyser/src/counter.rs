@@ -1,7 +1,11 @@
-use serde::{Serialize, Serializer};
+use std::ops::{AddAssign, SubAssign};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use counter_derive::CounterOps;
 use pai::yaku::Yaku;
 
-#[derive(Debug, Serialize, Default, Clone)]
+use crate::defense::{DealInProfile, PushFoldProfile};
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, CounterOps)]
 pub struct Counter {
     /// 场数
     pub matches: u32,
@@ -144,6 +148,11 @@ pub struct Counter {
     /// 各役种出现次数
     pub yakus: YakuCounter,
 
+    /// 放铳的详细汇总
+    pub deal_in: DealInProfile,
+    /// 面对立直时的押退
+    pub push_fold: PushFoldProfile,
+
     /// 总计R值
     pub tot_rate: f32,
 }
@@ -179,6 +188,18 @@ impl Default for YakuCounter {
     }
 }
 
+impl AddAssign<&YakuCounter> for YakuCounter {
+    fn add_assign(&mut self, rhs: &YakuCounter) {
+        self.iter_mut().zip(rhs.iter()).for_each(|(x, y)| *x += y);
+    }
+}
+
+impl SubAssign<&YakuCounter> for YakuCounter {
+    fn sub_assign(&mut self, rhs: &YakuCounter) {
+        self.iter_mut().zip(rhs.iter()).for_each(|(x, y)| *x -= y);
+    }
+}
+
 impl Serialize for YakuCounter {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -186,4 +207,16 @@ impl Serialize for YakuCounter {
     {
         self.yakus.serialize(serializer)
     }
-}
\ No newline at end of file
+}
+
+impl<'de> Deserialize<'de> for YakuCounter {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let values = Vec::<u32>::deserialize(deserializer)?;
+        let mut counter = YakuCounter::default();
+        counter.iter_mut().zip(values).for_each(|(x, y)| *x = y);
+        Ok(counter)
+    }
+}
@@ -0,0 +1,128 @@
+//! A rough deal-in danger of every tile kind against every opponent, from the classic
+//! reads of [`safety`](crate::safety) applied to what one seat can see of the table.
+//!
+//! The estimates are relative weights, not probabilities: `0` is a tile the opponent
+//! cannot ron on, and a musuji 4 to 6 is the most dangerous at `10`.
+
+use crate::safety::{is_genbutsu, is_honor, is_kabe, is_one_chance, is_suji, number};
+
+/// Discards before this many turns count as early for the outside-tile read.
+pub const EARLY_TURNS: usize = 6;
+
+/// 一名对手在桌面上可见的信息, 牌均以种类 (0..34) 表示
+#[derive(Debug, Clone, Default)]
+pub struct OpponentView {
+    /// 按打出顺序的牌河, 包括被鸣走的牌
+    pub river: Vec<usize>,
+    /// 立直宣言牌在牌河中的下标
+    pub riichi: Option<usize>,
+    /// 立直后其他家打出的牌
+    pub passed: Vec<usize>,
+}
+
+/// What one seat sees of the table. Calls only count through `visible`.
+#[derive(Debug, Clone)]
+pub struct Board {
+    /// 观察者的座位
+    pub seat: u8,
+    /// 各座位的对手, 观察者自己的一项不使用
+    pub opponents: [OpponentView; 4],
+    /// 观察者能看到的各种牌的张数: 自己的手牌, 牌河, 副露和宝牌指示牌
+    pub visible: [u8; 34],
+}
+
+impl Board {
+    /// The danger of every kind against every seat, `[seat][kind]`;
+    /// the row of the observing seat is all zero.
+    pub fn danger_table(&self) -> [[f32; 34]; 4] {
+        let mut table = [[0.; 34]; 4];
+        for (seat, row) in table.iter_mut().enumerate() {
+            if seat != self.seat as usize {
+                *row = core::array::from_fn(|kind| self.danger(seat as u8, kind));
+            }
+        }
+        table
+    }
+
+    /// The danger of discarding `kind` against `seat`.
+    pub fn danger(&self, seat: u8, kind: usize) -> f32 {
+        let opponent = &self.opponents[seat as usize];
+        let safe = opponent
+            .river
+            .iter()
+            .chain(opponent.passed.iter())
+            .copied()
+            .collect::<Vec<_>>();
+        if is_genbutsu(&safe, kind) {
+            return 0.;
+        }
+        if is_honor(kind) {
+            // Only a tanki or shanpon can wait on an honor, less likely with fewer left.
+            return match self.visible[kind] {
+                3.. => 0.5,
+                2 => 2.,
+                1 => 4.,
+                _ => 5.,
+            };
+        }
+        if is_kabe(&self.visible, kind) {
+            return 1.5;
+        }
+        let n = number(kind).unwrap();
+        let mut danger = if is_suji(&safe, kind) {
+            match n {
+                1 | 9 => 1.5,
+                2 | 8 => 2.5,
+                3 | 7 => 3.5,
+                _ => 3.,
+            }
+        } else {
+            match n {
+                1 | 9 => 5.,
+                2 | 8 => 7.,
+                3 | 7 => 8.,
+                _ => 10.,
+            }
+        };
+        if is_one_chance(&self.visible, kind) {
+            danger *= 0.6;
+        }
+        if opponent.is_early_outside(kind) {
+            danger *= 0.8;
+        }
+        if opponent.is_beside_riichi_tile(kind) {
+            danger *= 1.2;
+        }
+        danger
+    }
+}
+
+impl OpponentView {
+    /// Whether `kind` lies outside a number tile discarded early in the same suit,
+    /// e.g. 1 and 2 after an early 3: a hand that let the 3 go rarely waits on them.
+    pub fn is_early_outside(&self, kind: usize) -> bool {
+        let Some(n) = number(kind) else {
+            return false;
+        };
+        let early = self.river.len().min(self.riichi.unwrap_or(usize::MAX)).min(EARLY_TURNS);
+        self.river[..early].iter().any(|&discard| {
+            let Some(m) = number(discard) else {
+                return false;
+            };
+            discard / 9 == kind / 9 && ((n < m && m <= 5) || (n > m && m >= 5))
+        })
+    }
+
+    /// Whether `kind` is within two of the riichi declaration tile in the same suit,
+    /// so that the declaration may have come from a shape waiting on it.
+    pub fn is_beside_riichi_tile(&self, kind: usize) -> bool {
+        let Some(declared) = self.riichi.and_then(|i| self.river.get(i).copied()) else {
+            return false;
+        };
+        number(declared).is_some()
+            && number(kind).is_some()
+            && declared / 9 == kind / 9
+            && declared != kind
+            && declared.abs_diff(kind) <= 2
+    }
+}
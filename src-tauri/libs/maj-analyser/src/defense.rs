@@ -0,0 +1,201 @@
+use counter_derive::CounterOps;
+use serde::{Deserialize, Serialize};
+
+use crate::safety::{is_honor, is_terminal};
+use crate::shanten::kind;
+
+/// One deal-in of a registered player, seen from their side of the table.
+#[derive(Debug, Clone, Serialize)]
+pub struct DealIn {
+    /// 放铳者
+    pub player: String,
+    pub seat: u8,
+    /// 和了者的座位
+    pub winner: u8,
+    /// 放铳牌 (0..136)
+    pub pai: u8,
+    /// 放铳时的巡目
+    pub junme: u8,
+    pub score: i32,
+    /// 和了者是否已立直
+    pub winner_reached: bool,
+    /// 打出放铳牌后自己手牌的向听数, 0 为听牌
+    pub shanten: i8,
+    /// 放铳牌是否为和了者的现物
+    pub genbutsu: bool,
+    /// 放铳牌是否为和了者牌河的筋
+    pub suji: bool,
+    /// 放铳牌是否为壁
+    pub kabe: bool,
+    /// 放铳时对和了者估计的危险度, 见 [`danger`](crate::danger)
+    pub danger: f32,
+    /// 剩余手牌中估计比放铳牌更安全的牌的种类数
+    pub safer_in_hand: u8,
+}
+
+impl DealIn {
+    /// Whether the tile was a 2 to 8 that neither suji, kabe nor genbutsu said was safe.
+    pub fn is_unread_middle(&self) -> bool {
+        let kind = kind(self.pai);
+        !(is_honor(kind) || is_terminal(kind) || self.suji || self.kabe || self.genbutsu)
+    }
+}
+
+/// 放铳的汇总, 用于复盘防守
+#[derive(Debug, Serialize, Deserialize, Default, Clone, CounterOps)]
+pub struct DealInProfile {
+    /// 放铳次数
+    pub count: u32,
+    /// 放铳总点数
+    pub total_score: u64,
+    /// 放铳总巡目
+    pub total_junme: u32,
+    /// 未听牌时放铳次数
+    pub not_tenpai: u32,
+    /// 放铳给立直者次数
+    pub to_riichi: u32,
+    /// 放铳给立直者且自己未听牌的次数
+    pub to_riichi_not_tenpai: u32,
+    /// 放铳牌为现物的次数
+    pub genbutsu: u32,
+    /// 放铳牌为筋的次数
+    pub suji: u32,
+    /// 放铳牌为壁的次数
+    pub kabe: u32,
+    /// 放铳牌为字牌的次数
+    pub honor: u32,
+    /// 放铳牌为幺九数牌的次数
+    pub terminal: u32,
+    /// 放铳牌为无筋无壁的中张牌的次数
+    pub unread_middle: u32,
+    /// 手中有估计更安全的牌却放铳的次数
+    pub safer_in_hand: u32,
+}
+
+impl DealInProfile {
+    pub fn add(&mut self, deal_in: &DealIn) {
+        let kind = kind(deal_in.pai);
+        self.count += 1;
+        self.total_score += deal_in.score as u64;
+        self.total_junme += deal_in.junme as u32;
+        self.not_tenpai += (deal_in.shanten > 0) as u32;
+        self.to_riichi += deal_in.winner_reached as u32;
+        self.to_riichi_not_tenpai += (deal_in.winner_reached && deal_in.shanten > 0) as u32;
+        self.genbutsu += deal_in.genbutsu as u32;
+        self.suji += deal_in.suji as u32;
+        self.kabe += deal_in.kabe as u32;
+        self.honor += is_honor(kind) as u32;
+        self.terminal += is_terminal(kind) as u32;
+        self.unread_middle += deal_in.is_unread_middle() as u32;
+        self.safer_in_hand += (deal_in.safer_in_hand > 0) as u32;
+    }
+}
+
+/// Estimated han from which a tenpai hand is counted as a mangan.
+pub const MANGAN_HAN: u8 = 5;
+
+/// How safe a discard was against a riichi, from safest to most dangerous.
+/// Suji and chances only apply to number tiles; honours are judged by the copies left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DangerClass {
+    Genbutsu,
+    Suji,
+    /// 已见三张以上的字牌, 只可能是单骑
+    HonorTanki,
+    NoChance,
+    OneChance,
+    /// 已见不足三张的字牌, 还可能是双碰
+    HonorShanpon,
+    Other,
+    /// 宝牌或宝牌附近的数牌, 且不属于以上任何一种
+    DoraAdjacent,
+}
+
+/// A discard of a registered player, not in riichi, while an opponent was.
+#[derive(Debug, Clone, Serialize)]
+pub struct RiichiDiscard {
+    pub player: String,
+    pub seat: u8,
+    /// 打出的牌 (0..136)
+    pub pai: u8,
+    pub junme: u8,
+    /// 立直者的座位
+    pub riichi: Vec<u8>,
+    /// 打牌前手牌的向听数, 0 为可以听牌
+    pub shanten_before: i8,
+    /// 打牌后手牌的向听数
+    pub shanten: i8,
+    /// 估计的翻数, 见 [`Game::estimate_han`](crate::game::Game::estimate_han)
+    pub han: u8,
+    /// 对最危险的立直者而言的危险度
+    pub danger: DangerClass,
+}
+
+impl RiichiDiscard {
+    /// Whether a non-genbutsu tile was discarded.
+    pub fn is_push(&self) -> bool {
+        self.danger != DangerClass::Genbutsu
+    }
+
+    /// Whether a hand that could stay tenpai for a mangan was broken up.
+    pub fn is_mangan_fold(&self) -> bool {
+        self.shanten_before <= 0 && self.han >= MANGAN_HAN && self.shanten > 0
+    }
+}
+
+/// 面对立直时的押退汇总
+#[derive(Debug, Serialize, Deserialize, Default, Clone, CounterOps)]
+pub struct PushFoldProfile {
+    /// 有人立直且自己未立直时的打牌次数
+    pub turns: u32,
+    /// 其中打出非现物的次数
+    pub pushes: u32,
+    /// 打出筋牌的次数
+    pub push_suji: u32,
+    /// 打出 No Chance 牌的次数
+    pub push_no_chance: u32,
+    /// 打出 One Chance 牌的次数
+    pub push_one_chance: u32,
+    /// 打出只可能单骑的字牌的次数
+    pub push_honor_tanki: u32,
+    /// 打出还可能双碰的字牌的次数
+    pub push_honor_shanpon: u32,
+    /// 打出其他危险牌的次数
+    pub push_other: u32,
+    /// 打出宝牌附近危险牌的次数
+    pub push_dora_adjacent: u32,
+    /// 听牌时押的次数
+    pub push_tenpai: u32,
+    /// 打牌前两向听以上仍押的次数
+    pub push_two_shanten: u32,
+    /// 可以听满贯的打牌次数
+    pub tenpai_mangan: u32,
+    /// 其中拆掉听牌的次数
+    pub fold_tenpai_mangan: u32,
+}
+
+impl PushFoldProfile {
+    pub fn add(&mut self, discard: &RiichiDiscard) {
+        self.turns += 1;
+        if discard.is_push() {
+            self.pushes += 1;
+            self.push_tenpai += (discard.shanten <= 0) as u32;
+            self.push_two_shanten += (discard.shanten_before >= 2) as u32;
+        }
+        match discard.danger {
+            DangerClass::Genbutsu => {}
+            DangerClass::Suji => self.push_suji += 1,
+            DangerClass::NoChance => self.push_no_chance += 1,
+            DangerClass::OneChance => self.push_one_chance += 1,
+            DangerClass::HonorTanki => self.push_honor_tanki += 1,
+            DangerClass::HonorShanpon => self.push_honor_shanpon += 1,
+            DangerClass::Other => self.push_other += 1,
+            DangerClass::DoraAdjacent => self.push_dora_adjacent += 1,
+        }
+        if discard.shanten_before <= 0 && discard.han >= MANGAN_HAN {
+            self.tenpai_mangan += 1;
+            self.fold_tenpai_mangan += discard.is_mangan_fold() as u32;
+        }
+    }
+}
@@ -1,9 +1,14 @@
-use std::cell::RefCell;
 use std::collections::HashMap;
-use std::rc::Rc;
 use tenhou_parser::maj_event::{MajEvent, NakiType};
 
 use crate::counter::Counter;
+use crate::danger::{Board, OpponentView};
+use crate::defense::{DangerClass, DealIn, RiichiDiscard};
+use crate::kyoku::{ranks, KyokuOutcome, KyokuRecord};
+use crate::safety::{
+    dora_of, is_dora_adjacent, is_genbutsu, is_honor, is_kabe, is_one_chance, is_suji, is_terminal,
+};
+use crate::shanten::{kind, shanten, to_counts};
 use pai::yaku::Yaku;
 
 #[derive(Debug, Clone, Default)]
@@ -16,40 +21,86 @@ pub struct Player {
     pub discards: Vec<u8>,
     pub score: i32,
     pub reached: bool,
+    /// 立直宣言牌在 `discards` 中的下标
+    pub reach_index: Option<usize>,
+    /// 立直后其他家打出且未被该玩家荣和的牌, 对该玩家同为现物
+    pub passed: Vec<u8>,
     pub id: String,
 }
 
 pub struct Game {
+    /// 局数, 1 到 4
     pub kyoku: u8,
+    /// 场风, 0 为东场
+    pub round_wind: u8,
+    /// 规定的最后一个场风, 东风战为 0, 东南战为 1
+    pub final_round_wind: u8,
     pub honba: u8,
     pub kyotaku: u8,
     pub oya: u8,
     pub dora_marker: [Option<u8>; 5],
     pub players: [Player; 4],
-    counters: [Option<Rc<RefCell<Counter>>>; 4],
-    pub registered_counters: HashMap<String, Rc<RefCell<Counter>>>,
+    /// 当前这一场中各座位的统计, 只有被登记的玩家才有
+    counters: [Option<Counter>; 4],
+    /// 各登记玩家已结束的场次的统计
+    pub registered_counters: HashMap<String, Counter>,
+    /// 各登记玩家的每一次放铳
+    pub deal_ins: Vec<DealIn>,
+    /// 各登记玩家面对立直时打出的非现物
+    pub riichi_discards: Vec<RiichiDiscard>,
+    /// 各登记玩家的每一局
+    pub kyoku_records: Vec<KyokuRecord>,
+    /// 当前这一局开局时的点数
+    start_scores: [i32; 4],
+    /// 当前这一局的第一条记录在 `kyoku_records` 中的下标
+    kyoku_start: usize,
 }
 
 impl Game {
-    pub fn create_counters(ids: Vec<impl ToString>) -> HashMap<String, Rc<RefCell<Counter>>> {
-        ids.into_iter()
-            .map(|x| (x.to_string(), Rc::new(RefCell::new(Counter::default()))))
-            .collect()
-    }
-
-    pub fn new(registered_counters: &HashMap<String, Rc<RefCell<Counter>>>) -> Self {
+    /// Creates a game that keeps statistics for the players named in `ids`.
+    ///
+    /// A `Game` owns its counters, so separate games can run on separate threads
+    /// and their [`into_counters`](Self::into_counters) be merged afterwards.
+    pub fn new(ids: impl IntoIterator<Item = impl ToString>) -> Self {
         Game {
             kyoku: 0,
+            round_wind: 0,
+            final_round_wind: 1,
             honba: 0,
             kyotaku: 0,
             oya: 0,
             dora_marker: [None; 5],
             players: core::array::from_fn(|_| Player::default()),
             counters: core::array::from_fn(|_| None),
-            registered_counters: registered_counters.clone(),
+            registered_counters: ids
+                .into_iter()
+                .map(|x| (x.to_string(), Counter::default()))
+                .collect(),
+            deal_ins: vec![],
+            riichi_discards: vec![],
+            kyoku_records: vec![],
+            start_scores: [0; 4],
+            kyoku_start: 0,
+        }
+    }
+
+    /// Merges the counters of the game in progress into the registered ones.
+    fn flush_counters(&mut self) {
+        for i in 0..4 {
+            if let Some(counter) = self.counters[i].take() {
+                if let Some(total) = self.registered_counters.get_mut(&self.players[i].id) {
+                    *total += counter;
+                }
+            }
         }
     }
 
+    /// Finishes the game in progress and returns the statistics of every registered player.
+    pub fn into_counters(mut self) -> HashMap<String, Counter> {
+        self.flush_counters();
+        self.registered_counters
+    }
+
     pub fn get_player(&self, player: u8) -> &Player {
         &self.players[player as usize]
     }
@@ -58,42 +109,306 @@ impl Game {
         &mut self.players[player as usize]
     }
 
+    /// Counts of each tile kind `seat` can see: their hand, every river and call,
+    /// and the dora markers.
+    pub fn visible_counts(&self, seat: u8) -> [u8; 34] {
+        let mut counts = to_counts(&self.get_player(seat).tehai);
+        let mut add = |pai: &u8| counts[kind(*pai)] += 1;
+        for player in self.players.iter() {
+            player.discards.iter().for_each(&mut add);
+            for furo in player.furo.iter() {
+                match furo {
+                    // The called tile is in the river already; a kakan adds its own tile to a pon.
+                    MajEvent::Naki {
+                        r#type: NakiType::Kakan,
+                        pai: Some(pai),
+                        ..
+                    } => add(pai),
+                    MajEvent::Naki { consumed, .. } => consumed.iter().for_each(&mut add),
+                    _ => {}
+                }
+            }
+        }
+        self.dora_marker.iter().flatten().for_each(add);
+        counts
+    }
+
+    /// The deal-in of `fromwho` into `actor` on `machi`, judged on the table as it is now.
+    fn deal_in(&self, actor: u8, fromwho: u8, machi: u8, score: i32) -> DealIn {
+        let player = self.get_player(fromwho);
+        let winner = self.get_player(actor);
+        let river = winner.discards.iter().map(|&p| kind(p)).collect::<Vec<_>>();
+        let kind = kind(machi);
+        let board = self.board(fromwho);
+        let danger = board.danger(actor, kind);
+        let hand = to_counts(&player.tehai);
+        DealIn {
+            player: player.id.clone(),
+            seat: fromwho,
+            winner: actor,
+            pai: machi,
+            junme: player.junme,
+            score,
+            winner_reached: winner.reached,
+            shanten: shanten(&to_counts(&player.tehai)),
+            genbutsu: is_genbutsu(&river, kind),
+            suji: is_suji(&river, kind),
+            kabe: is_kabe(&board.visible, kind),
+            danger,
+            safer_in_hand: (0..34)
+                .filter(|&k| hand[k] > 0 && board.danger(actor, k) < danger)
+                .count() as u8,
+        }
+    }
+
+    /// The table as `seat` sees it, for the [`danger`](crate::danger) estimates.
+    pub fn board(&self, seat: u8) -> Board {
+        Board {
+            seat,
+            opponents: core::array::from_fn(|i| {
+                let player = &self.players[i];
+                OpponentView {
+                    river: player.discards.iter().map(|&p| kind(p)).collect(),
+                    riichi: player.reach_index,
+                    passed: player.passed.iter().map(|&p| kind(p)).collect(),
+                }
+            }),
+            visible: self.visible_counts(seat),
+        }
+    }
+
+    /// Kinds of the current doras.
+    pub fn dora_kinds(&self) -> Vec<usize> {
+        self.dora_marker
+            .iter()
+            .flatten()
+            .map(|&marker| dora_of(kind(marker)))
+            .collect()
+    }
+
+    /// A rough count of the han of `seat`'s hand as it stands: dora, red fives, riichi
+    /// if the hand is closed, value honor triplets and tanyao.
+    pub fn estimate_han(&self, seat: u8) -> u8 {
+        let player = self.get_player(seat);
+        let mut pais = player.tehai.clone();
+        let mut menzen = true;
+        for furo in player.furo.iter() {
+            if let MajEvent::Naki {
+                r#type,
+                consumed,
+                pai,
+                ..
+            } = furo
+            {
+                pais.extend(consumed.iter().chain(pai.iter()));
+                menzen &= r#type == &NakiType::Ankan;
+            }
+        }
+        let counts = to_counts(&pais);
+        let dora = self.dora_kinds().iter().map(|&k| counts[k]).sum::<u8>();
+        let aka = pais.iter().filter(|&&p| matches!(p, 16 | 52 | 88)).count() as u8;
+        let seat_wind = 27 + (seat + 4 - self.oya) as usize % 4;
+        let round_wind = 27 + self.round_wind as usize;
+        let yakuhai = [31, 32, 33, seat_wind, round_wind]
+            .iter()
+            .filter(|&&k| counts[k] >= 3)
+            .count() as u8;
+        let tanyao = (0..34).all(|k| counts[k] == 0 || !(k >= 27 || is_terminal(k)));
+        dora + aka + menzen as u8 + yakuhai + tanyao as u8
+    }
+
+    /// How `actor`, not in riichi, discarding `pai` while someone else is, looks on the
+    /// table as it is before the discard. `None` when nobody else is in riichi.
+    fn riichi_discard(&self, actor: u8, pai: u8) -> Option<RiichiDiscard> {
+        let player = self.get_player(actor);
+        let riichi = (0..4u8)
+            .filter(|&i| i != actor && self.get_player(i).reached)
+            .collect::<Vec<_>>();
+        if player.reached || riichi.is_empty() {
+            return None;
+        }
+        let tile = kind(pai);
+        let visible = self.visible_counts(actor);
+        let doras = self.dora_kinds();
+        let danger = riichi
+            .iter()
+            .map(|&seat| {
+                let opponent = self.get_player(seat);
+                let safe = opponent
+                    .discards
+                    .iter()
+                    .chain(opponent.passed.iter())
+                    .map(|&p| kind(p))
+                    .collect::<Vec<_>>();
+                if is_genbutsu(&safe, tile) {
+                    DangerClass::Genbutsu
+                } else if is_honor(tile) && visible[tile] >= 3 {
+                    DangerClass::HonorTanki
+                } else if is_honor(tile) && !doras.contains(&tile) {
+                    DangerClass::HonorShanpon
+                } else if is_suji(&safe, tile) {
+                    DangerClass::Suji
+                } else if is_kabe(&visible, tile) {
+                    DangerClass::NoChance
+                } else if is_one_chance(&visible, tile) {
+                    DangerClass::OneChance
+                } else if is_dora_adjacent(&doras, tile) {
+                    DangerClass::DoraAdjacent
+                } else {
+                    DangerClass::Other
+                }
+            })
+            .max()?;
+        let mut counts = to_counts(&player.tehai);
+        let shanten_before = shanten(&counts);
+        counts[tile] = counts[tile].saturating_sub(1);
+        Some(RiichiDiscard {
+            player: player.id.clone(),
+            seat: actor,
+            pai,
+            junme: player.junme + 1,
+            riichi,
+            shanten_before,
+            shanten: shanten(&counts),
+            han: self.estimate_han(actor),
+            danger,
+        })
+    }
+
+    /// Records the round ended by `e` for every registered seat. The second win of
+    /// a double ron updates the records of the first.
+    fn record_kyoku(&mut self, e: &MajEvent) {
+        let (after_scores, agari, owari) = match e {
+            MajEvent::Agari {
+                actor,
+                fromwho,
+                after_scores,
+                owari,
+                ..
+            } => (after_scores, Some((*actor, *fromwho)), *owari),
+            MajEvent::Ryuukyoku {
+                after_scores,
+                owari,
+                ..
+            } => (after_scores, None, *owari),
+            _ => return,
+        };
+        let ranks_before = ranks(&self.start_scores);
+        let ranks_after = ranks(after_scores);
+        let (winner, loser) = match agari {
+            Some((actor, fromwho)) => (Some(actor), (actor != fromwho).then_some(fromwho)),
+            None => (None, None),
+        };
+        for seat in 0..4u8 {
+            let i = seat as usize;
+            if self.counters[i].is_none() {
+                continue;
+            }
+            let outcome = match agari {
+                Some((actor, fromwho)) => KyokuOutcome::of_agari(seat, actor, fromwho),
+                None => KyokuOutcome::Draw,
+            };
+            let delta = after_scores[i] - self.start_scores[i];
+            if let Some(record) = self.kyoku_records[self.kyoku_start..]
+                .iter_mut()
+                .find(|r| r.seat == seat)
+            {
+                if !record.outcome.is_involved() {
+                    (record.outcome, record.winner, record.loser) = (outcome, winner, loser);
+                }
+                record.score_after = after_scores[i];
+                record.rank_after = ranks_after[i];
+                record.delta = delta;
+                record.last = owari;
+                continue;
+            }
+            let player = &self.players[i];
+            let record = KyokuRecord {
+                player: player.id.clone(),
+                seat,
+                round_wind: self.round_wind,
+                kyoku: self.kyoku,
+                honba: self.honba,
+                kyotaku: self.kyotaku,
+                seat_wind: (seat + 4 - self.oya) % 4,
+                score_before: self.start_scores[i],
+                rank_before: ranks_before[i],
+                lead: self.start_scores[i]
+                    - (0..4)
+                        .filter(|&j| j != i)
+                        .map(|j| self.start_scores[j])
+                        .max()
+                        .unwrap(),
+                score_after: after_scores[i],
+                rank_after: ranks_after[i],
+                riichi: player.reached,
+                called: player.furo.iter().any(|furo| {
+                    !matches!(
+                        furo,
+                        MajEvent::Naki {
+                            r#type: NakiType::Ankan,
+                            ..
+                        }
+                    )
+                }),
+                outcome,
+                delta,
+                winner,
+                loser,
+                last: owari,
+                all_last: self.round_wind == self.final_round_wind && self.kyoku == 4,
+            };
+            self.kyoku_records.push(record);
+        }
+    }
+
     pub fn on_event(&mut self, e: MajEvent) {
+        self.record_kyoku(&e);
         match e {
+            MajEvent::Go { r#type } => {
+                self.final_round_wind = r#type.is_south as u8;
+            }
             MajEvent::UN { dan, rate, ref id } => {
+                self.flush_counters();
                 for i in 0..4 {
                     let player = self.get_player_mut(i as u8);
                     player.dan = dan[i];
                     player.rate = rate[i];
                     player.id = id[i].clone();
-                    if self.registered_counters.contains_key(&id[i]) {
-                        self.counters[i] = Some(self.registered_counters[&id[i]].clone());
+                    self.counters[i] = if self.registered_counters.contains_key(&id[i]) {
+                        Some(Counter::default())
                     } else {
-                        self.counters[i] = None;
-                    }
+                        None
+                    };
                 }
             }
             MajEvent::Init {
                 dora_marker,
                 honba,
+                round_wind,
                 kyoku,
                 kyotaku,
                 oya,
                 scores,
                 tehais,
             } => {
+                self.round_wind = round_wind;
                 self.kyoku = kyoku;
                 self.honba = honba;
                 self.kyotaku = kyotaku;
                 self.oya = oya;
+                self.start_scores = scores;
+                self.kyoku_start = self.kyoku_records.len();
                 for i in 0..4 {
                     let player = self.get_player_mut(i as u8);
                     player.tehai = tehais[i].to_vec();
                     player.score = scores[i];
                     player.reached = false;
+                    player.reach_index = None;
                     player.furo.clear();
                     player.junme = 0;
                     player.discards.clear();
+                    player.passed.clear();
                 }
                 self.dora_marker = [None; 5];
                 self.dora_marker[0] = Some(dora_marker);
@@ -121,12 +436,36 @@ impl Game {
                 actor,
                 after_scores,
             } => {
-                self.get_player_mut(actor).reached = true;
+                let player = self.get_player_mut(actor);
+                player.reached = true;
+                player.reach_index = player.discards.len().checked_sub(1);
                 self.get_player_mut(actor).score = after_scores[actor as usize];
             }
+            MajEvent::Tsumo { actor, pai } => {
+                self.get_player_mut(actor).tehai.push(pai);
+            }
             MajEvent::Dahai { actor, pai } => {
-                self.get_player_mut(actor).junme += 1;
-                self.get_player_mut(actor).discards.push(pai);
+                if self.counters[actor as usize].is_some() {
+                    if let Some(discard) = self.riichi_discard(actor, pai) {
+                        if let Some(counter) = self.counters[actor as usize].as_mut() {
+                            counter.push_fold.add(&discard);
+                        }
+                        if discard.is_push() {
+                            self.riichi_discards.push(discard);
+                        }
+                    }
+                }
+                for other in self.players.iter_mut() {
+                    if other.reached {
+                        other.passed.push(pai);
+                    }
+                }
+                let player = self.get_player_mut(actor);
+                player.junme += 1;
+                player.discards.push(pai);
+                if let Some(i) = player.tehai.iter().position(|&x| x == pai) {
+                    player.tehai.remove(i);
+                }
             }
             MajEvent::Naki {
                 actor,
@@ -135,11 +474,16 @@ impl Game {
                 target,
                 r#type,
             } => {
+                // A kakan takes the added tile from the hand, the pon tiles are already out of it.
+                let from_hand = match (r#type, pai) {
+                    (NakiType::Kakan, Some(pai)) => vec![pai],
+                    _ => consumed.clone(),
+                };
                 self.get_player_mut(actor).tehai = self
                     .get_player(actor)
                     .tehai
                     .iter()
-                    .filter(|&x| !consumed.contains(x))
+                    .filter(|&x| !from_hand.contains(x))
                     .cloned()
                     .collect();
                 self.get_player_mut(actor).furo.push(MajEvent::Naki {
@@ -150,7 +494,26 @@ impl Game {
                     r#type,
                 });
             }
-            MajEvent::Agari { after_scores, .. } => {
+            MajEvent::Agari {
+                actor,
+                fromwho,
+                machi,
+                score,
+                after_scores,
+                ..
+            } => {
+                // The winning tile was taken as passed when discarded, but it did not pass.
+                let winner = self.get_player_mut(actor);
+                if let Some(i) = winner.passed.iter().rposition(|&p| p == machi) {
+                    winner.passed.remove(i);
+                }
+                if actor != fromwho && self.counters[fromwho as usize].is_some() {
+                    let deal_in = self.deal_in(actor, fromwho, machi, score);
+                    if let Some(counter) = self.counters[fromwho as usize].as_mut() {
+                        counter.deal_in.add(&deal_in);
+                    }
+                    self.deal_ins.push(deal_in);
+                }
                 for i in 0..4 {
                     self.get_player_mut(i as u8).score = after_scores[i];
                 }
@@ -173,9 +536,7 @@ impl Game {
             scores.sort_by(|a, b| b.1.cmp(&a.1));
             let mean_rate = (self.players.iter().map(|x| x.rate).sum::<f32>() / 4.0).max(1500.0);
             for (rank, &(i, score)) in scores.iter().enumerate() {
-                let counter = self.counters[i].clone();
-                if let Some(counter) = counter {
-                    let mut counter = counter.borrow_mut();
+                if let Some(counter) = self.counters[i].as_mut() {
                     match rank {
                         0 => {
                             counter.rank1 += 1;
@@ -200,6 +561,7 @@ impl Game {
                     }
                 }
             }
+            self.flush_counters();
         }
     }
 
@@ -207,18 +569,14 @@ impl Game {
         match e {
             MajEvent::UN { .. } => {
                 for i in 0..4 {
-                    let counter = self.counters[i].as_ref();
-                    if let Some(counter) = counter {
-                        let mut counter = counter.borrow_mut();
+                    if let Some(counter) = self.counters[i].as_mut() {
                         counter.matches += 1;
                     }
                 }
             }
             MajEvent::Init { .. } => {
                 for i in 0..4 {
-                    let counter = self.counters[i].as_ref();
-                    if let Some(counter) = counter {
-                        let mut counter = counter.borrow_mut();
+                    if let Some(counter) = self.counters[i].as_mut() {
                         counter.rounds += 1;
                     }
                 }
@@ -238,9 +596,7 @@ impl Game {
                     .map(|(i, _x)| i as u8)
                     .collect::<Vec<u8>>();
                 for i in 0..4 {
-                    let counter = self.counters[i as usize].as_ref();
-                    if let Some(counter) = counter {
-                        let mut counter = counter.borrow_mut();
+                    if let Some(counter) = self.counters[i as usize].as_mut() {
                         if i == actor {
                             counter.riichi += 1;
                             counter.riichi_double +=
@@ -281,16 +637,13 @@ impl Game {
                 }
                 for i in 0..4 {
                     let reached = self.get_player(i as u8).reached;
-                    let counter = self.counters[i].as_ref();
-                    if let Some(counter) = counter {
-                        let mut counter = counter.borrow_mut();
+                    if let Some(counter) = self.counters[i].as_mut() {
                         counter.draw += 1;
                         counter.draw_total_score += diff_scores[i] as i64;
                         counter.total_score += diff_scores[i] as i64;
                         counter.draw_tenpai += if tenpai[i] { 1 } else { 0 };
                         counter.riichi_draw += if reached { 1 } else { 0 };
-                        counter.total_furo += if self
-                            .get_player(i as u8)
+                        counter.total_furo += if self.players[i]
                             .furo
                             .iter()
                             .filter(|e| {
@@ -341,9 +694,7 @@ impl Game {
                     .collect::<Vec<bool>>();
                 for i in 0..4 {
                     let junme = self.get_player(i as u8).junme;
-                    let counter = self.counters[i].as_ref();
-                    if let Some(counter) = counter {
-                        let mut counter = counter.borrow_mut();
+                    if let Some(counter) = self.counters[i].as_mut() {
                         counter.total_score += diff_scores[i] as i64;
                         if i as u8 == actor {
                             counter.wins += 1;
@@ -453,8 +804,7 @@ impl Game {
                         } else {
                             counter.no_change += 1;
                         }
-                        counter.total_furo += if self
-                            .get_player(i as u8)
+                        counter.total_furo += if self.players[i]
                             .furo
                             .iter()
                             .filter(|e| {
@@ -0,0 +1,109 @@
+//! One round seen from the side of a registered player, with the standings around it.
+
+use serde::{Deserialize, Serialize};
+
+/// How a round ended for one player.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KyokuOutcome {
+    Tsumo,
+    Ron,
+    DealIn,
+    /// 被其他家自摸
+    OtherTsumo,
+    /// 其他两家之间的荣和
+    OtherRon,
+    /// 流局, 包括途中流局
+    Draw,
+}
+
+impl KyokuOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KyokuOutcome::Tsumo => "tsumo",
+            KyokuOutcome::Ron => "ron",
+            KyokuOutcome::DealIn => "deal_in",
+            KyokuOutcome::OtherTsumo => "other_tsumo",
+            KyokuOutcome::OtherRon => "other_ron",
+            KyokuOutcome::Draw => "draw",
+        }
+    }
+
+    /// The outcome for `seat` of a win of `actor` off `fromwho`.
+    pub fn of_agari(seat: u8, actor: u8, fromwho: u8) -> Self {
+        match (actor == seat, fromwho == seat, actor == fromwho) {
+            (true, _, true) => KyokuOutcome::Tsumo,
+            (true, _, false) => KyokuOutcome::Ron,
+            (false, true, _) => KyokuOutcome::DealIn,
+            (false, false, true) => KyokuOutcome::OtherTsumo,
+            (false, false, false) => KyokuOutcome::OtherRon,
+        }
+    }
+
+    /// Whether the player themselves won or dealt in.
+    pub fn is_involved(&self) -> bool {
+        matches!(
+            self,
+            KyokuOutcome::Tsumo | KyokuOutcome::Ron | KyokuOutcome::DealIn
+        )
+    }
+}
+
+/// 一名登记玩家的一局
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KyokuRecord {
+    pub player: String,
+    pub seat: u8,
+    /// 场风, 0 为东场, 1 为南场, 2 为西场
+    pub round_wind: u8,
+    /// 局数, 1 到 4
+    pub kyoku: u8,
+    pub honba: u8,
+    /// 开局时场上的立直棒
+    pub kyotaku: u8,
+    /// 自风, 0 为东即庄家
+    pub seat_wind: u8,
+    /// 开局时的点数
+    pub score_before: i32,
+    /// 开局时的顺位, 0 为一位
+    pub rank_before: u8,
+    /// 开局时与其他家最高点数之差, 领先时为正
+    pub lead: i32,
+    /// 结束时的点数
+    pub score_after: i32,
+    /// 结束时的顺位, 0 为一位
+    pub rank_after: u8,
+    /// 是否立直
+    pub riichi: bool,
+    /// 是否副露, 暗杠不算
+    pub called: bool,
+    pub outcome: KyokuOutcome,
+    /// 点数收支, 包括本场与立直棒
+    pub delta: i32,
+    /// 和了者, 一炮双响时自己和了则为自己, 否则为第一家
+    pub winner: Option<u8>,
+    /// 放铳者, 自摸或流局时为 `None`
+    pub loser: Option<u8>,
+    /// 是否为这一场的最后一局
+    pub last: bool,
+    /// 是否为 all-last, 即南四局, 东风战为东四局; 西入后的局不算
+    pub all_last: bool,
+}
+
+impl KyokuRecord {
+    pub fn is_dealer(&self) -> bool {
+        self.seat_wind == 0
+    }
+}
+
+/// Ranks of the four seats for `scores`, 0 being first.
+/// Ties go to the seat nearer to the first dealer, seat 0.
+pub fn ranks(scores: &[i32; 4]) -> [u8; 4] {
+    let mut order = [0, 1, 2, 3];
+    order.sort_by(|&a, &b| scores[b].cmp(&scores[a]));
+    let mut ranks = [0; 4];
+    for (rank, &seat) in order.iter().enumerate() {
+        ranks[seat] = rank as u8;
+    }
+    ranks
+}
@@ -1,17 +1,24 @@
 pub mod counter;
+pub mod danger;
+pub mod defense;
 pub mod game;
+pub mod kyoku;
+pub mod parallel;
+pub mod safety;
+pub mod shanten;
 
 #[cfg(test)]
 mod tests {
+    use crate::counter::Counter;
     use crate::game::Game;
+    use pai::yaku::Yaku;
     use std::path::PathBuf;
     use tenhou_parser::event_emitter::parse_file_iter;
 
     #[test]
     fn test() {
         let path = PathBuf::from(r#"D:\Projects\tenhou-log-manager\src-tauri\logs"#);
-        let counters = Game::create_counters(vec!["Rikaka"]);
-        let mut game = Game::new(&counters);
+        let mut game = Game::new(["Rikaka"]);
         path.read_dir().unwrap().for_each(|entry| {
             let entry = entry.unwrap();
             let path = entry.path();
@@ -22,7 +29,8 @@ mod tests {
                 });
             }
         });
-        let counter = counters.get("Rikaka").unwrap().borrow();
+        let counters = game.into_counters();
+        let counter = counters.get("Rikaka").unwrap();
         println!(
             r#"
         总场数: {}
@@ -54,4 +62,212 @@ mod tests {
             counter.tot_rate / counter.matches as f32 * 40.0
         );
     }
+
+    #[test]
+    fn counter_ops() {
+        let mut a = Counter {
+            matches: 3,
+            total_score: -1200,
+            tot_rate: 1.5,
+            ..Default::default()
+        };
+        *a.yakus.get_mut(Yaku::Riichi) = 2;
+        let mut b = Counter {
+            matches: 1,
+            total_score: 8000,
+            ..Default::default()
+        };
+        *b.yakus.get_mut(Yaku::Riichi) = 1;
+
+        let sum: Counter = vec![a.clone(), b.clone()].into_iter().sum();
+        assert_eq!(sum.matches, 4);
+        assert_eq!(sum.total_score, 6800);
+        assert_eq!(sum.yakus.get(Yaku::Riichi), 3);
+
+        let diff = sum - b;
+        assert_eq!(diff.matches, a.matches);
+        assert_eq!(diff.total_score, a.total_score);
+        assert_eq!(diff.tot_rate, a.tot_rate);
+        assert_eq!(diff.yakus.get(Yaku::Riichi), 2);
+    }
+
+    /// Counts from a hand like `123m456p11z`.
+    fn counts(hand: &str) -> [u8; 34] {
+        let mut counts = [0; 34];
+        let mut digits = vec![];
+        for c in hand.chars() {
+            match c {
+                '0'..='9' => digits.push(c.to_digit(10).unwrap() as usize),
+                suit => {
+                    let base = "mpsz".find(suit).unwrap() * 9;
+                    digits.drain(..).for_each(|n| counts[base + n - 1] += 1);
+                }
+            }
+        }
+        counts
+    }
+
+    #[test]
+    fn shanten() {
+        use crate::shanten::shanten;
+
+        assert_eq!(shanten(&counts("123456789m1234p")), 0);
+        assert_eq!(shanten(&counts("123456789m123p11z")), -1);
+        assert_eq!(shanten(&counts("147m147p147s1234z")), 6);
+        assert_eq!(shanten(&counts("1122m3344p5566s7z")), 0);
+        assert_eq!(shanten(&counts("19m19p19s1234567z")), 0);
+        assert_eq!(shanten(&counts("12m46p789s12345z")), 4);
+        // One call: three melds and a tanki in the hand.
+        assert_eq!(shanten(&counts("123456789m1p")), 0);
+    }
+
+    #[test]
+    fn safety() {
+        use crate::safety::{
+            dora_of, is_dora_adjacent, is_genbutsu, is_kabe, is_one_chance, is_suji,
+        };
+
+        // 1m and 7m discarded.
+        let river = [0, 6];
+        assert!(is_genbutsu(&river, 6));
+        assert!(is_suji(&river, 3));
+        assert!(!is_suji(&river, 4));
+        assert!(!is_suji(&river, 27));
+
+        let mut visible = counts("2222m");
+        assert!(is_kabe(&visible, 0));
+        assert!(!is_kabe(&visible, 2));
+        visible[3] = 4;
+        assert!(is_kabe(&visible, 2));
+        assert!(is_one_chance(&counts("222m"), 0));
+        assert!(!is_one_chance(&counts("22m"), 0));
+
+        assert_eq!((dora_of(8), dora_of(13), dora_of(30), dora_of(33)), (0, 14, 27, 31));
+        assert!(is_dora_adjacent(&[14], 12));
+        assert!(!is_dora_adjacent(&[14], 11));
+        assert!(!is_dora_adjacent(&[17], 18));
+    }
+
+    /// B deals in to A's dama on the first discard.
+    const DEAL_IN_LOG: &str = r#"<mjloggm ver="2.3"><GO type="169" lobby="0"/><UN n0="%41" n1="%42" n2="%43" n3="%44" dan="16,15,14,13" rate="2100.00,2000.00,1900.00,1800.00" sx="M,M,M,M"/><TAIKYOKU oya="0"/><INIT seed="0,0,0,2,3,52" ten="250,250,250,250" oya="0" hai0="0,4,8,12,16,20,24,28,32,36,40,44,48" hai1="1,5,9,13,17,21,25,29,33,37,41,45,49" hai2="2,6,10,14,18,22,26,30,34,38,42,46,50" hai3="3,7,11,15,19,23,27,31,35,39,43,47,51"/><T60/><D60/><U61/><E61/><AGARI ba="0,0" hai="0,4,8,12,16,20,24,28,32,36,40,44,48,61" machi="61" ten="30,12000,1" yaku="8,1,52,1" doraHai="52" who="0" fromWho="1" sc="250,120,250,-120,250,0,250,0" owari="370,47.0,130,-27.0,250,-5.0,250,-15.0"/></mjloggm>"#;
+
+    /// B pushes a tile into A's riichi and deals in with it.
+    const RIICHI_DEAL_IN_LOG: &str = r#"<mjloggm ver="2.3"><GO type="169" lobby="0"/><UN n0="%41" n1="%42" n2="%43" n3="%44" dan="16,15,14,13" rate="2100.00,2000.00,1900.00,1800.00" sx="M,M,M,M"/><TAIKYOKU oya="0"/><INIT seed="0,0,0,2,3,52" ten="250,250,250,250" oya="0" hai0="0,4,8,12,16,20,24,28,32,36,40,44,48" hai1="1,5,9,13,17,21,25,29,33,37,41,45,49" hai2="2,6,10,14,18,22,26,30,34,38,42,46,50" hai3="3,7,11,15,19,23,27,31,35,39,43,47,51"/><T60/><REACH who="0" step="1"/><D60/><REACH who="0" ten="240,250,250,250" step="2"/><U62/><E1/><AGARI ba="0,1" hai="0,1,4,8,12,16,20,24,28,32,36,40,44,48" machi="1" ten="30,12000,1" yaku="1,1,8,1" doraHai="52" who="0" fromWho="1" sc="240,130,250,-120,250,0,250,0" owari="370,47.0,130,-27.0,250,-5.0,250,-15.0"/></mjloggm>"#;
+
+    #[test]
+    fn danger() {
+        use crate::danger::{Board, OpponentView};
+
+        let mut visible = counts("111z3333s");
+        visible[3] += 1;
+        let board = Board {
+            seat: 0,
+            opponents: [
+                OpponentView::default(),
+                // 4m early, riichi on 5p.
+                OpponentView {
+                    river: vec![3, 27, 13],
+                    riichi: Some(2),
+                    passed: vec![],
+                },
+                OpponentView::default(),
+                OpponentView::default(),
+            ],
+            visible,
+        };
+        let danger = board.danger_table();
+        assert!(danger[0].iter().all(|&d| d == 0.));
+        assert_eq!(danger[1][3], 0.);
+        assert_eq!(danger[1][0], 1.5 * 0.8);
+        assert_eq!(danger[1][6], 3.5);
+        assert_eq!(danger[1][4], 10.);
+        assert_eq!(danger[1][12], 10. * 1.2);
+        assert_eq!(danger[1][18], 1.5);
+        assert_eq!(danger[1][28], 5.);
+        assert_eq!(danger[2][27], 0.5);
+    }
+
+    #[test]
+    fn deal_in() {
+        let events = tenhou_parser::event_emitter::try_parse_bytes(DEAL_IN_LOG.as_bytes()).unwrap();
+        let mut game = Game::new(["B"]);
+        events.into_iter().for_each(|e| game.on_event(e));
+        assert_eq!(game.get_player(1).tehai.len(), 13);
+        assert_eq!(game.deal_ins.len(), 1);
+        let deal_in = &game.deal_ins[0];
+        assert_eq!((deal_in.winner, deal_in.pai, deal_in.junme), (0, 61, 1));
+        assert_eq!(deal_in.shanten, 0);
+        assert!(deal_in.genbutsu && !deal_in.suji && !deal_in.kabe && !deal_in.winner_reached);
+        assert_eq!((deal_in.danger, deal_in.safer_in_hand), (0., 0));
+
+        let counter = game.into_counters().remove("B").unwrap();
+        assert_eq!((counter.deal_in.count, counter.deal_in.total_score), (1, 12000));
+        assert_eq!((counter.deal_in.genbutsu, counter.deal_in.not_tenpai), (1, 0));
+    }
+
+    #[test]
+    fn riichi_deal_in() {
+        let events = tenhou_parser::event_emitter::try_parse_bytes(RIICHI_DEAL_IN_LOG.as_bytes()).unwrap();
+        let mut game = Game::new(["B"]);
+        events.into_iter().for_each(|e| game.on_event(e));
+        // The ron tile never went through, so it is neither passed nor genbutsu.
+        assert!(game.get_player(0).passed.is_empty());
+        let deal_in = &game.deal_ins[0];
+        assert_eq!((deal_in.pai, deal_in.winner_reached), (1, true));
+        assert!(!deal_in.genbutsu && deal_in.danger > 0.);
+    }
+
+    #[test]
+    fn kyoku_records() {
+        use crate::kyoku::KyokuOutcome;
+
+        let events = tenhou_parser::event_emitter::try_parse_bytes(DEAL_IN_LOG.as_bytes()).unwrap();
+        let mut game = Game::new(["A", "B"]);
+        events.into_iter().for_each(|e| game.on_event(e));
+        assert_eq!(game.kyoku_records.len(), 2);
+        let (a, b) = (&game.kyoku_records[0], &game.kyoku_records[1]);
+        assert_eq!((a.player.as_str(), a.outcome, a.delta), ("A", KyokuOutcome::Ron, 12000));
+        assert_eq!((a.round_wind, a.kyoku, a.seat_wind), (0, 1, 0));
+        assert_eq!((a.rank_before, a.rank_after), (0, 0));
+        assert_eq!((b.outcome, b.delta, b.seat_wind), (KyokuOutcome::DealIn, -12000, 1));
+        assert_eq!((b.rank_before, b.rank_after, b.score_after), (1, 3, 13000));
+        assert_eq!((b.winner, b.loser, b.lead), (Some(0), Some(1), 0));
+        assert!(b.last && !b.all_last && !b.riichi && !b.called && !b.is_dealer());
+    }
+
+    #[test]
+    fn push_fold() {
+        use crate::defense::{DangerClass, PushFoldProfile, RiichiDiscard};
+
+        let events = tenhou_parser::event_emitter::try_parse_bytes(RIICHI_DEAL_IN_LOG.as_bytes()).unwrap();
+        let mut game = Game::new(["B"]);
+        events.into_iter().for_each(|e| game.on_event(e));
+        assert_eq!(game.riichi_discards.len(), 1);
+        let discard = &game.riichi_discards[0];
+        assert_eq!((discard.pai, discard.riichi.clone()), (1, vec![0]));
+        assert_eq!((discard.shanten_before, discard.shanten), (0, 1));
+        assert_eq!((discard.han, discard.danger), (1, DangerClass::Other));
+
+        // Breaking tenpai is not a push from two-shanten, advancing from two-shanten is.
+        let mut profile = PushFoldProfile::default();
+        profile.add(discard);
+        profile.add(&RiichiDiscard { shanten_before: 2, shanten: 1, ..discard.clone() });
+        assert_eq!((profile.pushes, profile.push_two_shanten), (2, 1));
+
+        let counter = game.into_counters().remove("B").unwrap();
+        assert_eq!((counter.push_fold.turns, counter.push_fold.pushes), (1, 1));
+        assert_eq!((counter.push_fold.push_other, counter.push_fold.tenpai_mangan), (1, 0));
+
+        // A live honour can still be a shanpon; with three copies out only a tanki is left.
+        let danger = |log: &str| {
+            let events = tenhou_parser::event_emitter::try_parse_bytes(log.as_bytes()).unwrap();
+            let mut game = Game::new(["B"]);
+            events.into_iter().for_each(|e| game.on_event(e));
+            game.riichi_discards[0].danger
+        };
+        let honor = RIICHI_DEAL_IN_LOG.replace("<U62/><E1/>", "<U108/><E108/>");
+        assert_eq!(danger(&honor), DangerClass::HonorShanpon);
+        let three_out = honor.replace(",45,49\"", ",45,109\"").replace("3,52\"", "3,110\"");
+        assert_eq!(danger(&three_out), DangerClass::HonorTanki);
+    }
 }
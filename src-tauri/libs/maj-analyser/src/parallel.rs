@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tenhou_parser::event_emitter::try_parse_file;
+
+use crate::counter::Counter;
+use crate::game::Game;
+
+/// Maps `f` over `items` on a pool of worker threads, keeping the order of `items`.
+pub fn par_map<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let threads = std::thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(items.len())
+        .max(1);
+    let next = AtomicUsize::new(0);
+    let mut results = std::thread::scope(|s| {
+        let workers = (0..threads)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut out = vec![];
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        if i >= items.len() {
+                            break;
+                        }
+                        out.push((i, f(&items[i])));
+                    }
+                    out
+                })
+            })
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .flat_map(|w| w.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, r)| r).collect()
+}
+
+/// Adds every counter of `other` into the counter of the same player in `into`.
+pub fn merge_counters(into: &mut HashMap<String, Counter>, other: HashMap<String, Counter>) {
+    for (id, counter) in other {
+        *into.entry(id).or_default() += counter;
+    }
+}
+
+/// Analyses the logs at `paths` in parallel and returns the statistics of the players in `ids`.
+/// Logs that fail to parse are skipped.
+pub fn analyse_files<P: AsRef<Path> + Sync>(
+    paths: &[P],
+    ids: &[String],
+) -> HashMap<String, Counter> {
+    let per_file = par_map(paths, |path| {
+        let events = try_parse_file(path).ok()?;
+        let mut game = Game::new(ids);
+        events.into_iter().for_each(|event| game.on_event(event));
+        Some(game.into_counters())
+    });
+    let mut counters = ids
+        .iter()
+        .map(|id| (id.clone(), Counter::default()))
+        .collect::<HashMap<_, _>>();
+    per_file
+        .into_iter()
+        .flatten()
+        .for_each(|c| merge_counters(&mut counters, c));
+    counters
+}
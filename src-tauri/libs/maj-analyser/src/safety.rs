@@ -0,0 +1,73 @@
+//! Classic reads of how safe a tile kind is against one opponent.
+//! Kinds are numbered as in [`shanten`](crate::shanten).
+
+/// Whether `kind` is an honor.
+pub fn is_honor(kind: usize) -> bool {
+    kind >= 27
+}
+
+/// 1 to 9 for number tiles, `None` for honors.
+pub fn number(kind: usize) -> Option<usize> {
+    (!is_honor(kind)).then_some(kind % 9 + 1)
+}
+
+/// Whether `kind` is a 1 or a 9.
+pub fn is_terminal(kind: usize) -> bool {
+    matches!(number(kind), Some(1 | 9))
+}
+
+/// Whether the opponent discarded `kind` themselves, so cannot ron on it.
+pub fn is_genbutsu(river: &[usize], kind: usize) -> bool {
+    river.contains(&kind)
+}
+
+/// Whether every two-sided wait on `kind` is ruled out by the opponent's discards,
+/// e.g. 1 and 7 by a discarded 4, and 5 by both 2 and 8.
+pub fn is_suji(river: &[usize], kind: usize) -> bool {
+    let Some(n) = number(kind) else {
+        return false;
+    };
+    let below = n <= 3 || river.contains(&(kind - 3));
+    let above = n >= 7 || river.contains(&(kind + 3));
+    below && above
+}
+
+/// Whether every two-sided wait on `kind` is ruled out because all four copies of a
+/// tile it needs are visible (no-chance), e.g. 3 with all the 4s or all the 5s out.
+pub fn is_kabe(visible: &[u8; 34], kind: usize) -> bool {
+    walled(visible, kind, 4)
+}
+
+/// Whether every two-sided wait on `kind` needs a tile only one copy of which is left.
+pub fn is_one_chance(visible: &[u8; 34], kind: usize) -> bool {
+    walled(visible, kind, 3)
+}
+
+fn walled(visible: &[u8; 34], kind: usize, copies: u8) -> bool {
+    let Some(n) = number(kind) else {
+        return false;
+    };
+    let gone = |offset: isize| visible[(kind as isize + offset) as usize] >= copies;
+    let below = n <= 3 || gone(-1) || gone(-2);
+    let above = n >= 7 || gone(1) || gone(2);
+    below && above
+}
+
+/// The dora kind indicated by a marker of kind `marker`.
+pub fn dora_of(marker: usize) -> usize {
+    match marker {
+        0..=26 if marker % 9 == 8 => marker - 8,
+        0..=26 => marker + 1,
+        30 => 27,
+        33 => 31,
+        _ => marker + 1,
+    }
+}
+
+/// Whether `kind` is a dora or a number tile at most two away from one,
+/// the tiles a hand keeps to use its dora.
+pub fn is_dora_adjacent(doras: &[usize], kind: usize) -> bool {
+    doras.iter().any(|&dora| {
+        dora == kind || (!is_honor(kind) && dora / 9 == kind / 9 && dora.abs_diff(kind) <= 2)
+    })
+}
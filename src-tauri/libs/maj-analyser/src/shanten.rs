@@ -0,0 +1,99 @@
+//! Shanten of a hand given as counts of the 34 tile kinds.
+//!
+//! Tiles in the logs are numbered 0..136, four of each kind; the kind is `pai / 4`,
+//! with 0..9 manzu, 9..18 pinzu, 18..27 souzu and 27..34 honors.
+//!
+//! The `shanten-calculator` crate is not used: it does not build against the current
+//! `pai` crate, and its tables are built or loaded from disk up front, a cost the analyser
+//! would pay on every start for the few hands it looks at per discard.
+//! A small search over the counts is enough here.
+
+/// The kind (0..34) of a tile of the logs (0..136).
+pub fn kind(pai: u8) -> usize {
+    pai as usize / 4
+}
+
+/// Counts of each kind among `pais`.
+pub fn to_counts(pais: &[u8]) -> [u8; 34] {
+    let mut counts = [0; 34];
+    pais.iter().for_each(|&pai| counts[kind(pai)] += 1);
+    counts
+}
+
+/// Shanten of the concealed tiles in `counts`, the missing tiles being called melds.
+/// `0` is tenpai and `-1` a complete hand.
+pub fn shanten(counts: &[u8; 34]) -> i8 {
+    let tiles = counts.iter().map(|&c| c as i8).sum::<i8>().min(14);
+    let called = ((14 - tiles) / 3) as u8;
+    let mut counts = *counts;
+    let mut best = 8;
+    search(&mut counts, 0, called, 0, false, &mut best);
+    if called == 0 {
+        best = best.min(chiitoi(&counts)).min(kokushi(&counts));
+    }
+    best
+}
+
+fn search(c: &mut [u8; 34], i: usize, mentsu: u8, taatsu: u8, pair: bool, best: &mut i8) {
+    let Some(i) = (i..34).find(|&i| c[i] > 0) else {
+        let taatsu = taatsu.min(4u8.saturating_sub(mentsu));
+        let value = 8 - 2 * mentsu as i8 - taatsu as i8 - pair as i8;
+        *best = (*best).min(value);
+        return;
+    };
+    let suited = i < 27;
+    let n = i % 9;
+    if c[i] >= 3 {
+        c[i] -= 3;
+        search(c, i, mentsu + 1, taatsu, pair, best);
+        c[i] += 3;
+    }
+    if suited && n <= 6 && c[i + 1] > 0 && c[i + 2] > 0 {
+        take(c, &[i, i + 1, i + 2]);
+        search(c, i, mentsu + 1, taatsu, pair, best);
+        give(c, &[i, i + 1, i + 2]);
+    }
+    if mentsu + taatsu < 4 || !pair {
+        if c[i] >= 2 {
+            c[i] -= 2;
+            if !pair {
+                search(c, i, mentsu, taatsu, true, best);
+            }
+            search(c, i, mentsu, taatsu + 1, pair, best);
+            c[i] += 2;
+        }
+        for d in [1, 2] {
+            if suited && n + d <= 8 && c[i + d] > 0 {
+                take(c, &[i, i + d]);
+                search(c, i, mentsu, taatsu + 1, pair, best);
+                give(c, &[i, i + d]);
+            }
+        }
+    }
+    // The tile is left isolated.
+    let count = c[i];
+    c[i] = 0;
+    search(c, i + 1, mentsu, taatsu, pair, best);
+    c[i] = count;
+}
+
+fn take(c: &mut [u8; 34], kinds: &[usize]) {
+    kinds.iter().for_each(|&k| c[k] -= 1);
+}
+
+fn give(c: &mut [u8; 34], kinds: &[usize]) {
+    kinds.iter().for_each(|&k| c[k] += 1);
+}
+
+fn chiitoi(c: &[u8; 34]) -> i8 {
+    let pairs = c.iter().filter(|&&x| x >= 2).count() as i8;
+    let kinds = c.iter().filter(|&&x| x > 0).count() as i8;
+    6 - pairs + (7 - kinds).max(0)
+}
+
+fn kokushi(c: &[u8; 34]) -> i8 {
+    let yaochu = [0, 8, 9, 17, 18, 26, 27, 28, 29, 30, 31, 32, 33];
+    let kinds = yaochu.iter().filter(|&&k| c[k] > 0).count() as i8;
+    let pair = yaochu.iter().any(|&k| c[k] >= 2);
+    13 - kinds - pair as i8
+}
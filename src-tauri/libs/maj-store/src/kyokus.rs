@@ -0,0 +1,44 @@
+//! Every round of a player, to break results down by the situation they were played in.
+
+use maj_analyser::kyoku::KyokuRecord;
+use serde::Serialize;
+use sqlite::State;
+
+use crate::profile::PlayerProfile;
+use crate::query::GameFilter;
+use crate::{LogStore, Result};
+
+/// 档案的一局
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileKyoku {
+    pub game_id: String,
+    #[serde(flatten)]
+    pub record: KyokuRecord,
+}
+
+impl LogStore {
+    /// The rounds of `profile` in the games matching `filter`, the most recent game first
+    /// and the rounds of a game in the order they were played.
+    pub fn kyoku_records(
+        &self,
+        profile: &PlayerProfile,
+        filter: &GameFilter,
+    ) -> Result<Vec<ProfileKyoku>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT record FROM kyoku_records WHERE game_id = ? AND seat = ? ORDER BY idx;",
+        )?;
+        let mut kyokus = vec![];
+        for game in self.profile_games(profile, filter)? {
+            stmt.reset()?;
+            stmt.bind((1, game.game_id.as_str()))?;
+            stmt.bind((2, game.seat as i64))?;
+            while let State::Row = stmt.next()? {
+                kyokus.push(ProfileKyoku {
+                    game_id: game.game_id.clone(),
+                    record: serde_json::from_str(&stmt.read::<String, _>(0)?)?,
+                });
+            }
+        }
+        Ok(kyokus)
+    }
+}
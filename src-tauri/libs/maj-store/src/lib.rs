@@ -0,0 +1,899 @@
+pub mod kyokus;
+pub mod manifest;
+pub mod opponents;
+pub mod profile;
+pub mod query;
+pub mod record;
+pub mod situations;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Debug, Display, Formatter};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use maj_analyser::parallel::par_map;
+use serde::Serialize;
+use sqlite::{Connection, State};
+use tenhou_parser::event_emitter::try_parse_file;
+use tenhou_parser::PARSER_VERSION;
+
+use crate::manifest::{content_hash, modified_secs, ManifestEntry};
+use crate::record::GameRecord;
+
+/// Bump when the schema or the way records are derived from logs changes.
+/// An outdated store is dropped and rebuilt from the logs on the next import.
+const SCHEMA_VERSION: i64 = 14;
+
+const TABLES: [&str; 6] = [
+    "games",
+    "players",
+    "kyoku_results",
+    "kyoku_records",
+    "player_stats",
+    "files",
+];
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS games (
+    id TEXT PRIMARY KEY,
+    played_at TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS players (
+    game_id TEXT NOT NULL,
+    seat INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    dan INTEGER NOT NULL,
+    rate REAL NOT NULL,
+    score INTEGER NOT NULL,
+    rank INTEGER NOT NULL,
+    PRIMARY KEY (game_id, seat)
+);
+CREATE INDEX IF NOT EXISTS players_name ON players (name);
+CREATE TABLE IF NOT EXISTS kyoku_results (
+    game_id TEXT NOT NULL,
+    idx INTEGER NOT NULL,
+    round_wind INTEGER NOT NULL,
+    kyoku INTEGER NOT NULL,
+    honba INTEGER NOT NULL,
+    kyotaku INTEGER NOT NULL,
+    oya INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    winner INTEGER,
+    loser INTEGER,
+    score INTEGER NOT NULL,
+    diff_scores TEXT NOT NULL,
+    PRIMARY KEY (game_id, idx)
+);
+CREATE TABLE IF NOT EXISTS kyoku_records (
+    game_id TEXT NOT NULL,
+    seat INTEGER NOT NULL,
+    idx INTEGER NOT NULL,
+    record TEXT NOT NULL,
+    PRIMARY KEY (game_id, seat, idx)
+);
+CREATE TABLE IF NOT EXISTS player_stats (
+    game_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    counter TEXT NOT NULL,
+    PRIMARY KEY (game_id, name)
+);
+CREATE INDEX IF NOT EXISTS player_stats_name ON player_stats (name);
+CREATE TABLE IF NOT EXISTS files (
+    path TEXT PRIMARY KEY,
+    id TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    modified INTEGER NOT NULL,
+    hash TEXT NOT NULL,
+    parser_version INTEGER NOT NULL,
+    error TEXT
+);
+-- Seat the user played in, from the `tw` of the log links. Not derived from the logs,
+-- so it is kept when the store is rebuilt.
+CREATE TABLE IF NOT EXISTS owners (
+    game_id TEXT PRIMARY KEY,
+    seat INTEGER NOT NULL
+);
+";
+
+pub enum StoreError {
+    Sqlite(sqlite::Error),
+    Io(std::io::Error),
+    Json(serde_json::Error),
+}
+
+impl From<sqlite::Error> for StoreError {
+    fn from(e: sqlite::Error) -> Self {
+        StoreError::Sqlite(e)
+    }
+}
+
+impl From<std::io::Error> for StoreError {
+    fn from(e: std::io::Error) -> Self {
+        StoreError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for StoreError {
+    fn from(e: serde_json::Error) -> Self {
+        StoreError::Json(e)
+    }
+}
+
+impl Debug for StoreError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StoreError::Sqlite(e) => write!(f, "SqliteError: {}", e),
+            StoreError::Io(e) => write!(f, "IoError: {}", e),
+            StoreError::Json(e) => write!(f, "JsonError: {}", e),
+        }
+    }
+}
+
+impl Display for StoreError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+pub type Result<T> = std::result::Result<T, StoreError>;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub imported: u64,
+    pub skipped: u64,
+    /// 无法解析的文件, 错误记录在 manifest 中, 文件改变前不再重试
+    pub failed: u64,
+    /// 已从目录中删除的文件
+    pub removed: u64,
+    /// 导入被取消, 未解析的文件留到下次导入
+    pub cancelled: bool,
+}
+
+/// Reported once for every parsed file of an import.
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportProgress {
+    pub done: u64,
+    /// 需要解析的文件数, 不含未改变的文件
+    pub total: u64,
+    /// 刚解析完的文件
+    pub current: String,
+    /// 解析失败时的错误信息
+    pub error: Option<String>,
+}
+
+/// The log id of a `<id>.xml` or `<id>.xml.gz` file.
+pub fn log_id(path: &Path) -> Option<&str> {
+    let name = path.file_name()?.to_str()?;
+    name.strip_suffix(".xml")
+        .or_else(|| name.strip_suffix(".xml.gz"))
+        .filter(|id| !id.is_empty())
+}
+
+/// SQLite backed store of everything the analyser needs from the logs folder,
+/// so that each log only has to be parsed once.
+pub struct LogStore {
+    conn: Connection,
+}
+
+impl LogStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let conn = sqlite::open(path)?;
+        let store = LogStore { conn };
+        store.migrate()?;
+        Ok(store)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::open(":memory:")
+    }
+
+    fn migrate(&self) -> Result<()> {
+        let mut stmt = self.conn.prepare("PRAGMA user_version;")?;
+        stmt.next()?;
+        let version = stmt.read::<i64, _>(0)?;
+        drop(stmt);
+        if version != SCHEMA_VERSION {
+            for table in TABLES {
+                self.conn.execute(format!("DROP TABLE IF EXISTS {};", table))?;
+            }
+        }
+        self.conn.execute(SCHEMA)?;
+        self.conn
+            .execute(format!("PRAGMA user_version = {};", SCHEMA_VERSION))?;
+        Ok(())
+    }
+
+    pub fn contains(&self, id: &str) -> Result<bool> {
+        let mut stmt = self.conn.prepare("SELECT 1 FROM games WHERE id = ?;")?;
+        stmt.bind((1, id))?;
+        Ok(stmt.next()? == State::Row)
+    }
+
+    pub fn game_ids(&self) -> Result<HashSet<String>> {
+        let mut stmt = self.conn.prepare("SELECT id FROM games;")?;
+        let mut ids = HashSet::new();
+        while let State::Row = stmt.next()? {
+            ids.insert(stmt.read::<String, _>(0)?);
+        }
+        Ok(ids)
+    }
+
+    /// Brings the store in line with the `.xml` and `.xml.gz` logs in `dir`.
+    ///
+    /// Only files that are new, changed, or were processed by an older parser are parsed,
+    /// on all available cores. The store is then updated in a single transaction.
+    /// Files that fail to parse are recorded with their error, and games whose file
+    /// disappeared are removed.
+    pub fn import_dir(&self, dir: impl AsRef<Path>) -> Result<ImportSummary> {
+        self.import_dirs_with(&[dir.as_ref()], |_| {}, &AtomicBool::new(false))
+    }
+
+    /// Like [`LogStore::import_dir`], calling `on_progress` after each parsed file.
+    /// Setting `cancel` stops parsing; what was parsed so far is still stored.
+    pub fn import_dir_with(
+        &self,
+        dir: impl AsRef<Path>,
+        on_progress: impl Fn(ImportProgress) + Sync,
+        cancel: &AtomicBool,
+    ) -> Result<ImportSummary> {
+        self.import_dirs_with(&[dir.as_ref()], on_progress, cancel)
+    }
+
+    /// Like [`LogStore::import_dir_with`] for several folders at once. Files recorded
+    /// from any other folder, e.g. a library root that was removed, are dropped, and a
+    /// game is only removed once no remaining file holds its log. The files of a folder
+    /// that is missing, e.g. on a drive that is not mounted, are kept as they are.
+    pub fn import_dirs_with(
+        &self,
+        dirs: &[&Path],
+        on_progress: impl Fn(ImportProgress) + Sync,
+        cancel: &AtomicBool,
+    ) -> Result<ImportSummary> {
+        let mut manifest = self.manifest()?;
+        let mut summary = ImportSummary::default();
+        let mut unchanged = vec![];
+        let mut pending = vec![];
+        // Ids of the files found, except those known to fail, whose games must stay.
+        let mut present = HashSet::new();
+        for dir in dirs {
+            if !dir.is_dir() {
+                manifest.retain(|path, entry| {
+                    let keep = Path::new(path).parent() == Some(dir);
+                    if keep && entry.error.is_none() {
+                        present.insert(entry.id.clone());
+                    }
+                    !keep
+                });
+                continue;
+            }
+            for entry in dir.read_dir()? {
+                let path = entry?.path();
+                let id = match log_id(&path) {
+                    Some(id) if path.is_file() => id.to_string(),
+                    _ => continue,
+                };
+                let path_str = path.to_string_lossy().to_string();
+                let metadata = path.metadata()?;
+                let (size, modified) = (metadata.len(), modified_secs(&metadata));
+                let known = manifest.remove(&path_str);
+                if known.as_ref().is_some_and(|k| k.is_fresh(size, modified)) {
+                    if known.is_some_and(|k| k.error.is_none()) {
+                        present.insert(id);
+                    }
+                    summary.skipped += 1;
+                    continue;
+                }
+                let hash = content_hash(&std::fs::read(&path)?);
+                let mut entry = ManifestEntry {
+                    path: path_str,
+                    id,
+                    size,
+                    modified,
+                    hash,
+                    parser_version: PARSER_VERSION,
+                    error: None,
+                };
+                if let Some(known) = known.filter(|k| k.is_same_content(size, hash)) {
+                    entry.error = known.error;
+                    if entry.error.is_none() {
+                        present.insert(entry.id.clone());
+                    }
+                    unchanged.push(entry);
+                } else {
+                    pending.push(entry);
+                }
+            }
+        }
+
+        let total = pending.len() as u64;
+        let done = AtomicU64::new(0);
+        let parsed = par_map(&pending, |entry| {
+            if cancel.load(Ordering::Relaxed) {
+                return None;
+            }
+            let record = try_parse_file(&entry.path)
+                .map(|events| GameRecord::from_events(&entry.id, events));
+            on_progress(ImportProgress {
+                done: done.fetch_add(1, Ordering::Relaxed) + 1,
+                total,
+                current: entry.path.clone(),
+                error: record.as_ref().err().cloned(),
+            });
+            Some(record)
+        });
+        summary.cancelled = parsed.iter().any(|record| record.is_none());
+        for (entry, record) in pending.iter().zip(parsed.iter()) {
+            // A cancelled file keeps whatever was stored for it before.
+            if !matches!(record, Some(Err(_))) {
+                present.insert(entry.id.clone());
+            }
+        }
+
+        self.transaction(|| {
+            for entry in unchanged.iter() {
+                self.put_file_inner(entry)?;
+                summary.skipped += 1;
+            }
+            for (mut entry, record) in pending.into_iter().zip(parsed) {
+                match record {
+                    None => continue,
+                    Some(Ok(record)) => {
+                        self.insert_inner(&record)?;
+                        summary.imported += 1;
+                    }
+                    Some(Err(e)) => {
+                        if !present.contains(&entry.id) {
+                            self.remove_inner(&entry.id)?;
+                        }
+                        entry.error = Some(e);
+                        summary.failed += 1;
+                    }
+                }
+                self.put_file_inner(&entry)?;
+            }
+            for (path, entry) in manifest {
+                if !present.contains(&entry.id) {
+                    self.remove_inner(&entry.id)?;
+                }
+                self.remove_file_inner(&path)?;
+                summary.removed += 1;
+            }
+            Ok(())
+        })?;
+        Ok(summary)
+    }
+
+    /// Inserts (or replaces) everything recorded for one game in a single transaction.
+    pub fn insert(&self, record: &GameRecord) -> Result<()> {
+        self.transaction(|| self.insert_inner(record))
+    }
+
+    fn transaction(&self, f: impl FnOnce() -> Result<()>) -> Result<()> {
+        self.conn.execute("BEGIN;")?;
+        match f() {
+            Ok(()) => {
+                self.conn.execute("COMMIT;")?;
+                Ok(())
+            }
+            Err(e) => {
+                self.conn.execute("ROLLBACK;")?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Remembers the seat the user played in for each `(id, seat)`.
+    /// The owner's stats are joined on these seats, so no game needs to be parsed again.
+    pub fn set_owner_seats(&self, seats: &[(String, u8)]) -> Result<()> {
+        self.transaction(|| {
+            for (id, seat) in seats {
+                let mut stmt = self
+                    .conn
+                    .prepare("INSERT OR REPLACE INTO owners (game_id, seat) VALUES (?, ?);")?;
+                stmt.bind((1, id.as_str()))?;
+                stmt.bind((2, *seat as i64))?;
+                stmt.next()?;
+            }
+            Ok(())
+        })
+    }
+
+    /// The seat of the user in every game it is known for, keyed by game id.
+    pub fn owner_seats(&self) -> Result<HashMap<String, u8>> {
+        let mut stmt = self.conn.prepare("SELECT game_id, seat FROM owners;")?;
+        let mut seats = HashMap::new();
+        while let State::Row = stmt.next()? {
+            seats.insert(stmt.read::<String, _>(0)?, stmt.read::<i64, _>(1)? as u8);
+        }
+        Ok(seats)
+    }
+
+    /// Every processed file, keyed by path.
+    pub fn manifest(&self) -> Result<HashMap<String, ManifestEntry>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT path, id, size, modified, hash, parser_version, error FROM files;",
+        )?;
+        let mut manifest = HashMap::new();
+        while let State::Row = stmt.next()? {
+            let entry = ManifestEntry {
+                path: stmt.read::<String, _>(0)?,
+                id: stmt.read::<String, _>(1)?,
+                size: stmt.read::<i64, _>(2)? as u64,
+                modified: stmt.read::<i64, _>(3)?,
+                hash: u64::from_str_radix(&stmt.read::<String, _>(4)?, 16).unwrap_or(0),
+                parser_version: stmt.read::<i64, _>(5)? as u32,
+                error: stmt.read::<Option<String>, _>(6)?,
+            };
+            manifest.insert(entry.path.clone(), entry);
+        }
+        Ok(manifest)
+    }
+
+    /// Files that could not be parsed, with their error.
+    pub fn failed_files(&self) -> Result<Vec<ManifestEntry>> {
+        let mut failed = self
+            .manifest()?
+            .into_values()
+            .filter(|entry| entry.error.is_some())
+            .collect::<Vec<_>>();
+        failed.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(failed)
+    }
+
+    fn put_file_inner(&self, entry: &ManifestEntry) -> Result<()> {
+        let mut stmt = self.conn.prepare(
+            "INSERT OR REPLACE INTO files (path, id, size, modified, hash, parser_version, error)
+             VALUES (?, ?, ?, ?, ?, ?, ?);",
+        )?;
+        stmt.bind((1, entry.path.as_str()))?;
+        stmt.bind((2, entry.id.as_str()))?;
+        stmt.bind((3, entry.size as i64))?;
+        stmt.bind((4, entry.modified))?;
+        stmt.bind((5, format!("{:016x}", entry.hash).as_str()))?;
+        stmt.bind((6, entry.parser_version as i64))?;
+        stmt.bind((7, entry.error.as_deref()))?;
+        stmt.next()?;
+        Ok(())
+    }
+
+    fn remove_file_inner(&self, path: &str) -> Result<()> {
+        let mut stmt = self.conn.prepare("DELETE FROM files WHERE path = ?;")?;
+        stmt.bind((1, path))?;
+        stmt.next()?;
+        Ok(())
+    }
+
+    fn insert_inner(&self, record: &GameRecord) -> Result<()> {
+        self.remove_inner(&record.id)?;
+
+        let mut stmt = self
+            .conn
+            .prepare("INSERT INTO games (id, played_at) VALUES (?, ?);")?;
+        stmt.bind((1, record.id.as_str()))?;
+        stmt.bind((2, record.played_at.as_str()))?;
+        stmt.next()?;
+
+        let mut stmt = self.conn.prepare(
+            "INSERT INTO players (game_id, seat, name, dan, rate, score, rank)
+             VALUES (?, ?, ?, ?, ?, ?, ?);",
+        )?;
+        for player in record.players.iter() {
+            stmt.reset()?;
+            stmt.bind((1, record.id.as_str()))?;
+            stmt.bind((2, player.seat as i64))?;
+            stmt.bind((3, player.name.as_str()))?;
+            stmt.bind((4, player.dan as i64))?;
+            stmt.bind((5, player.rate as f64))?;
+            stmt.bind((6, player.score as i64))?;
+            stmt.bind((7, player.rank as i64))?;
+            stmt.next()?;
+        }
+
+        let mut stmt = self.conn.prepare(
+            "INSERT INTO kyoku_results
+             (game_id, idx, round_wind, kyoku, honba, kyotaku, oya, kind, winner, loser, score,
+              diff_scores)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);",
+        )?;
+        for (idx, result) in record.results.iter().enumerate() {
+            stmt.reset()?;
+            stmt.bind((1, record.id.as_str()))?;
+            stmt.bind((2, idx as i64))?;
+            stmt.bind((3, result.round_wind as i64))?;
+            stmt.bind((4, result.kyoku as i64))?;
+            stmt.bind((5, result.honba as i64))?;
+            stmt.bind((6, result.kyotaku as i64))?;
+            stmt.bind((7, result.oya as i64))?;
+            stmt.bind((8, result.kind.as_str()))?;
+            stmt.bind((9, result.winner.map(|x| x as i64)))?;
+            stmt.bind((10, result.loser.map(|x| x as i64)))?;
+            stmt.bind((11, result.score as i64))?;
+            stmt.bind((12, serde_json::to_string(&result.diff_scores)?.as_str()))?;
+            stmt.next()?;
+        }
+
+        let mut stmt = self.conn.prepare(
+            "INSERT INTO kyoku_records (game_id, seat, idx, record) VALUES (?, ?, ?, ?);",
+        )?;
+        for (idx, kyoku) in record.kyokus.iter().enumerate() {
+            stmt.reset()?;
+            stmt.bind((1, record.id.as_str()))?;
+            stmt.bind((2, kyoku.seat as i64))?;
+            stmt.bind((3, idx as i64))?;
+            stmt.bind((4, serde_json::to_string(kyoku)?.as_str()))?;
+            stmt.next()?;
+        }
+
+        let mut stmt = self
+            .conn
+            .prepare("INSERT INTO player_stats (game_id, name, counter) VALUES (?, ?, ?);")?;
+        for (name, counter) in record.stats.iter() {
+            stmt.reset()?;
+            stmt.bind((1, record.id.as_str()))?;
+            stmt.bind((2, name.as_str()))?;
+            stmt.bind((3, serde_json::to_string(counter)?.as_str()))?;
+            stmt.next()?;
+        }
+        Ok(())
+    }
+
+    fn remove_inner(&self, id: &str) -> Result<()> {
+        for table in ["games", "players", "kyoku_results", "kyoku_records", "player_stats"] {
+            let column = if table == "games" { "id" } else { "game_id" };
+            let mut stmt = self
+                .conn
+                .prepare(format!("DELETE FROM {} WHERE {} = ?;", table, column))?;
+            stmt.bind((1, id))?;
+            stmt.next()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::GameFilter;
+
+    const LOG: &str = r#"<mjloggm ver="2.3"><SHUFFLE seed="" ref=""/><GO type="169" lobby="0"/><UN n0="%41" n1="%42" n2="%43" n3="%44" dan="16,15,14,13" rate="2100.00,2000.00,1900.00,1800.00" sx="M,M,M,M"/><TAIKYOKU oya="0"/><INIT seed="0,0,0,2,3,52" ten="250,250,250,250" oya="0" hai0="0,4,8,12,16,20,24,28,32,36,40,44,48" hai1="1,5,9,13,17,21,25,29,33,37,41,45,49" hai2="2,6,10,14,18,22,26,30,34,38,42,46,50" hai3="3,7,11,15,19,23,27,31,35,39,43,47,51"/><T60/><D60/><U61/><E61/><AGARI ba="0,0" hai="0,4,8,12,16,20,24,28,32,36,40,44,48,61" machi="61" ten="30,12000,1" yaku="8,1,52,1" doraHai="52" who="0" fromWho="1" sc="250,120,250,-120,250,0,250,0" owari="370,47.0,130,-27.0,250,-5.0,250,-15.0"/></mjloggm>"#;
+
+    #[test]
+    fn import_and_query() {
+        let dir = std::env::temp_dir().join("maj-store-import-and-query");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("2024072317gm-0089-0000-87c78500.xml"), LOG).unwrap();
+
+        let store = LogStore::open_in_memory().unwrap();
+        let summary = store.import_dir(&dir).unwrap();
+        assert_eq!(summary.imported, 1);
+        let summary = store.import_dir(&dir).unwrap();
+        assert_eq!(summary.imported, 0);
+        assert_eq!(summary.skipped, 1);
+
+        let a = store.player_counter("A").unwrap();
+        assert_eq!(a.matches, 1);
+        assert_eq!(a.rank1, 1);
+        assert_eq!(a.wins, 1);
+        let b = store.player_counter("B").unwrap();
+        assert_eq!(b.loses, 1);
+        assert_eq!(b.rank4, 1);
+        assert!(store.contains("2024072317gm-0089-0000-87c78500").unwrap());
+
+        let filter: GameFilter = "since=2024-07-24".parse().unwrap();
+        assert_eq!(store.player_counter_filtered("A", &filter).unwrap().matches, 0);
+        let filter: GameFilter = "since=20240723,until=20240723,last=5".parse().unwrap();
+        assert_eq!(store.player_counter_filtered("A", &filter).unwrap().matches, 1);
+        let games = store.player_games("B", &filter).unwrap();
+        assert_eq!(games.len(), 1);
+        assert_eq!((games[0].seat, games[0].rank, games[0].score), (1, 3, 13000));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn incremental_import() {
+        let dir = std::env::temp_dir().join("maj-store-incremental-import");
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("2024072317gm-0089-0000-87c78500.xml");
+        let broken = dir.join("2024072318gm-0089-0000-00000000.xml");
+        std::fs::write(&log, LOG).unwrap();
+        std::fs::write(&broken, "<mjloggm ver=\"2.3\"><GO type=\"169\"/><BROKEN/></mjloggm>").unwrap();
+
+        let store = LogStore::open_in_memory().unwrap();
+        let summary = store.import_dir(&dir).unwrap();
+        assert_eq!((summary.imported, summary.failed), (1, 1));
+        let failed = store.failed_files().unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].id, "2024072318gm-0089-0000-00000000");
+        assert!(failed[0].error.is_some());
+
+        // Broken files are not retried until they change.
+        let summary = store.import_dir(&dir).unwrap();
+        assert_eq!((summary.imported, summary.skipped, summary.failed), (0, 2, 0));
+
+        std::fs::write(&broken, LOG.replace("%41", "%45")).unwrap();
+        std::fs::remove_file(&log).unwrap();
+        let summary = store.import_dir(&dir).unwrap();
+        assert_eq!((summary.imported, summary.removed), (1, 1));
+        assert!(store.failed_files().unwrap().is_empty());
+        assert_eq!(store.player_counter("A").unwrap().matches, 0);
+        assert_eq!(store.player_counter("E").unwrap().matches, 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn several_roots() {
+        let root = std::env::temp_dir().join("maj-store-several-roots");
+        let (a, b) = (root.join("a"), root.join("b"));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&a).unwrap();
+        std::fs::create_dir_all(&b).unwrap();
+        let name = "2024072317gm-0089-0000-87c78500.xml";
+        std::fs::write(a.join(name), LOG).unwrap();
+        std::fs::write(b.join(name), LOG).unwrap();
+        std::fs::write(b.join("2024072318gm-0089-0000-87c78500.xml"), LOG).unwrap();
+
+        let store = LogStore::open_in_memory().unwrap();
+        let import = |dirs: &[&Path]| {
+            store
+                .import_dirs_with(dirs, |_| {}, &AtomicBool::new(false))
+                .unwrap()
+        };
+        assert_eq!(import(&[&a, &b]).imported, 3);
+        assert_eq!(store.game_ids().unwrap().len(), 2);
+
+        // The same log is still in `b`.
+        std::fs::remove_file(a.join(name)).unwrap();
+        assert_eq!(import(&[&a, &b]).removed, 1);
+        assert!(store.contains("2024072317gm-0089-0000-87c78500").unwrap());
+
+        // A root that is not mounted keeps its games, one that was dropped loses them.
+        let moved = root.join("moved");
+        std::fs::rename(&b, &moved).unwrap();
+        assert_eq!(import(&[&a, &b]).removed, 0);
+        assert_eq!(store.game_ids().unwrap().len(), 2);
+        assert_eq!(import(&[&a]).removed, 2);
+        assert!(store.game_ids().unwrap().is_empty());
+        assert!(store.manifest().unwrap().is_empty());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn owner_seats() {
+        use crate::profile::PlayerProfile;
+
+        let dir = std::env::temp_dir().join("maj-store-owner-seats");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("2024072317gm-0089-0000-87c78500.xml"), LOG).unwrap();
+        std::fs::write(
+            dir.join("2024072318gm-0089-0000-87c78500.xml"),
+            LOG.replace("%42", "%45"),
+        )
+        .unwrap();
+
+        let store = LogStore::open_in_memory().unwrap();
+        assert_eq!(store.import_dir(&dir).unwrap().imported, 2);
+        assert_eq!(store.owner_counter(&GameFilter::default()).unwrap().matches, 0);
+
+        // B played the second game as E; the seats apply without parsing the games again.
+        store
+            .set_owner_seats(&[
+                ("2024072317gm-0089-0000-87c78500".to_string(), 1),
+                ("2024072318gm-0089-0000-87c78500".to_string(), 1),
+            ])
+            .unwrap();
+        assert_eq!(store.import_dir(&dir).unwrap().imported, 0);
+        let owner = store.owner_counter(&GameFilter::default()).unwrap();
+        assert_eq!((owner.matches, owner.loses, owner.rank4), (2, 2, 2));
+        assert_eq!(store.player_counter("B").unwrap().matches, 1);
+        let games = store.owner_games(&GameFilter::default()).unwrap();
+        assert_eq!(games.iter().map(|g| g.seat).collect::<Vec<_>>(), [1, 1]);
+        let guess = store.most_frequent_player().unwrap().unwrap();
+        assert!(guess == "B" || guess == "E");
+
+        let owner = PlayerProfile::owner();
+        let stats = store.profile_stats(&owner, &GameFilter::default()).unwrap();
+        assert_eq!((stats.total.matches, stats.aliases.len()), (2, 0));
+        let kyokus = store.kyoku_records(&owner, &GameFilter::default()).unwrap();
+        assert_eq!(kyokus.iter().map(|k| k.record.seat).collect::<Vec<_>>(), [1, 1]);
+        assert_eq!(store.situation_stats(&owner, &GameFilter::default()).unwrap().games, 2);
+        let index = store.opponent_index(&owner, &GameFilter::default()).unwrap();
+        assert_eq!(index.opponents[0].games, 2);
+
+        // Aliases come first; the owner seat adds the games under other names.
+        let profile = PlayerProfile {
+            owner: true,
+            ..PlayerProfile::from("B")
+        };
+        let stats = store.profile_stats(&profile, &GameFilter::default()).unwrap();
+        assert_eq!((stats.total.matches, stats.aliases[0].counter.matches), (2, 1));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn profiles() {
+        use crate::profile::{Alias, PlayerProfile};
+
+        let dir = std::env::temp_dir().join("maj-store-profiles");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("2024072317gm-0089-0000-87c78500.xml"), LOG).unwrap();
+        std::fs::write(
+            dir.join("2024072318gm-0089-0000-87c78500.xml"),
+            LOG.replace("%41", "%45"),
+        )
+        .unwrap();
+        let store = LogStore::open_in_memory().unwrap();
+        store.import_dir(&dir).unwrap();
+
+        let alias: Alias = "A@2024-07-23..2024072317".parse().unwrap();
+        assert_eq!(alias.since.as_deref(), Some("20240723"));
+        assert_eq!(alias.to_string(), "A@20240723..2024072317");
+        assert_eq!("a@b".parse::<Alias>().unwrap().name, "a@b");
+        assert!("@..2024".parse::<Alias>().is_err());
+
+        let profile = PlayerProfile {
+            name: "me".to_string(),
+            aliases: vec!["A".parse().unwrap(), "E@2024072318..".parse().unwrap()],
+            owner: false,
+        };
+        let stats = store.profile_stats(&profile, &GameFilter::default()).unwrap();
+        assert_eq!((stats.total.matches, stats.total.wins), (2, 2));
+        assert_eq!(stats.aliases.iter().map(|a| a.counter.matches).collect::<Vec<_>>(), [1, 1]);
+
+        let stats = store.profile_stats(&profile, &"last=1".parse().unwrap()).unwrap();
+        assert_eq!(stats.total.matches, 1);
+        assert_eq!(stats.aliases[1].counter.matches, 1);
+
+        let profile = PlayerProfile {
+            name: "me".to_string(),
+            aliases: vec!["E@..2024072317".parse().unwrap()],
+            owner: false,
+        };
+        let stats = store.profile_stats(&profile, &GameFilter::default()).unwrap();
+        assert_eq!(stats.total.matches, 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn opponents() {
+        let store = LogStore::open_in_memory().unwrap();
+        for (id, log) in [
+            ("2024072317gm-0089-0000-87c78500", LOG.to_string()),
+            ("2024072318gm-0089-0000-87c78500", LOG.replace("%43", "%45")),
+        ] {
+            let events = tenhou_parser::event_emitter::try_parse_bytes(log.as_bytes()).unwrap();
+            store.insert(&GameRecord::from_events(id, events)).unwrap();
+        }
+
+        let index = store.opponent_index(&"B".into(), &GameFilter::default()).unwrap();
+        let names = index.opponents.iter().map(|o| o.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["A", "D", "C", "E"]);
+        let a = &index.opponents[0];
+        assert_eq!((a.games, a.above, a.rank_sum), (2, 0, 8));
+        assert_eq!((a.dealt_in_to, a.dealt_in_to_score, a.total_delta), (2, 24000, -24000));
+        assert_eq!(index.opponents[1].dealt_in_to, 0);
+        assert_eq!(index.rate_bands.len(), 1);
+        assert_eq!((index.rate_bands[0].min, index.rate_bands[0].ranks), (1900, [0, 0, 0, 2]));
+
+        let index = store.opponent_index(&"A".into(), &"last=1".parse().unwrap()).unwrap();
+        assert_eq!(index.opponents.len(), 3);
+        assert_eq!((index.opponents[0].won_from, index.opponents[0].won_from_score), (1, 12000));
+    }
+
+    #[test]
+    fn opponents_with_uncollected_riichi() {
+        let store = LogStore::open_in_memory().unwrap();
+        // A declares riichi and the game ends in a draw with the stick left on the table.
+        let draw = r#"<T60/><REACH who="0" step="1"/><D60/><REACH who="0" ten="240,250,250,250" step="2"/><RYUUKYOKU ba="0,1" sc="240,0,250,0,250,0,250,0" owari="240,-16.0,250,5.0,250,-5.0,250,-15.0"/></mjloggm>"#;
+        let log = LOG[..LOG.find("<T60/>").unwrap()].to_string() + draw;
+        let events = tenhou_parser::event_emitter::try_parse_bytes(log.as_bytes()).unwrap();
+        store
+            .insert(&GameRecord::from_events("2024072317gm-0089-0000-87c78500", events))
+            .unwrap();
+
+        let index = store.opponent_index(&"A".into(), &GameFilter::default()).unwrap();
+        assert!(index.opponents.iter().all(|o| o.total_delta == -1000));
+        assert_eq!(index.rate_bands[0].total_delta, -1000);
+    }
+
+    #[test]
+    fn kyoku_records() {
+        use maj_analyser::kyoku::KyokuOutcome;
+
+        let store = LogStore::open_in_memory().unwrap();
+        let events = tenhou_parser::event_emitter::try_parse_bytes(LOG.as_bytes()).unwrap();
+        let id = "2024072317gm-0089-0000-87c78500";
+        store.insert(&GameRecord::from_events(id, events)).unwrap();
+
+        let kyokus = store.kyoku_records(&"B".into(), &GameFilter::default()).unwrap();
+        assert_eq!(kyokus.len(), 1);
+        assert_eq!(kyokus[0].game_id, id);
+        let record = &kyokus[0].record;
+        assert_eq!((record.seat, record.outcome, record.delta), (1, KyokuOutcome::DealIn, -12000));
+        assert_eq!((record.rank_before, record.rank_after), (1, 3));
+        assert!(store.kyoku_records(&"F".into(), &GameFilter::default()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn situations() {
+        let store = LogStore::open_in_memory().unwrap();
+        // A game ending in East 1, A dealing in at South 4, and A winning East 4 of an east-only game.
+        let south_4 = LOG.replace("%41", "%45").replace("%42", "%41").replace("seed=\"0,", "seed=\"7,");
+        let east_4 = LOG.replace("type=\"169\"", "type=\"161\"").replace("seed=\"0,", "seed=\"3,");
+        for (id, log) in [
+            ("2024072317gm-0089-0000-87c78500", LOG.to_string()),
+            ("2024072318gm-0089-0000-87c78500", south_4),
+            ("2024072319gm-00a9-0000-87c78500", east_4),
+        ] {
+            let events = tenhou_parser::event_emitter::try_parse_bytes(log.as_bytes()).unwrap();
+            store.insert(&GameRecord::from_events(id, events)).unwrap();
+        }
+
+        let mut stmt = store
+            .conn
+            .prepare("SELECT round_wind, kyoku FROM kyoku_results WHERE game_id = ?;")
+            .unwrap();
+        stmt.bind((1, "2024072318gm-0089-0000-87c78500")).unwrap();
+        stmt.next().unwrap();
+        assert_eq!((stmt.read::<i64, _>(0).unwrap(), stmt.read::<i64, _>(1).unwrap()), (1, 4));
+
+        let stats = store.situation_stats(&"A".into(), &GameFilter::default()).unwrap();
+        assert_eq!((stats.games, stats.decided_in_all_last), (3, 1));
+        assert_eq!(stats.all_last.iter().flatten().sum::<u32>(), 2);
+        assert_eq!((stats.all_last[0][0], stats.all_last[1][3]), (1, 1));
+        assert_eq!((stats.dealer.rounds, stats.dealer.wins), (2, 2));
+        assert_eq!((stats.non_dealer.deal_ins, stats.non_dealer.total_delta), (1, -12000));
+        assert_eq!((stats.close.rounds, stats.leading.rounds), (3, 0));
+        assert_eq!((stats.all_last_rounds.rounds, stats.all_last_rounds.wins), (2, 1));
+    }
+
+    #[test]
+    fn compressed_logs() {
+        use std::io::Write;
+
+        let dir = std::env::temp_dir().join("maj-store-compressed-logs");
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(LOG.as_bytes()).unwrap();
+        std::fs::write(
+            dir.join("2024072317gm-0089-0000-87c78500.xml.gz"),
+            encoder.finish().unwrap(),
+        )
+        .unwrap();
+
+        let store = LogStore::open_in_memory().unwrap();
+        assert_eq!(store.import_dir(&dir).unwrap().imported, 1);
+        assert!(store.contains("2024072317gm-0089-0000-87c78500").unwrap());
+        assert_eq!(store.player_counter("A").unwrap().wins, 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn progress_and_cancel() {
+        let dir = std::env::temp_dir().join("maj-store-progress-and-cancel");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("2024072317gm-0089-0000-87c78500.xml"), LOG).unwrap();
+        std::fs::write(dir.join("2024072318gm-0089-0000-87c78500.xml"), LOG).unwrap();
+
+        let store = LogStore::open_in_memory().unwrap();
+        let summary = store
+            .import_dir_with(&dir, |_| panic!("nothing should be parsed"), &AtomicBool::new(true))
+            .unwrap();
+        assert!(summary.cancelled);
+        assert_eq!(summary.imported, 0);
+        assert!(store.manifest().unwrap().is_empty());
+
+        let progress = std::sync::Mutex::new(vec![]);
+        let summary = store
+            .import_dir_with(&dir, |p| progress.lock().unwrap().push(p), &AtomicBool::new(false))
+            .unwrap();
+        assert!(!summary.cancelled);
+        assert_eq!(summary.imported, 2);
+        let mut progress = progress.into_inner().unwrap();
+        progress.sort_by_key(|p| p.done);
+        assert_eq!(progress.iter().map(|p| (p.done, p.total)).collect::<Vec<_>>(), [(1, 2), (2, 2)]);
+        assert!(progress.iter().all(|p| p.error.is_none()));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
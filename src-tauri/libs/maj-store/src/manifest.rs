@@ -0,0 +1,49 @@
+use std::fs::Metadata;
+use std::time::UNIX_EPOCH;
+
+use serde::Serialize;
+use tenhou_parser::PARSER_VERSION;
+
+/// 已处理过的牌谱文件
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ManifestEntry {
+    pub path: String,
+    /// 牌谱 id, 即文件名去掉扩展名
+    pub id: String,
+    pub size: u64,
+    /// 修改时间, unix 秒
+    pub modified: i64,
+    /// 文件内容的 FNV-1a 哈希
+    pub hash: u64,
+    /// 处理时的 [`PARSER_VERSION`]
+    pub parser_version: u32,
+    /// 解析失败时的错误信息
+    pub error: Option<String>,
+}
+
+impl ManifestEntry {
+    /// Whether the file can be skipped without even reading it.
+    pub fn is_fresh(&self, size: u64, modified: i64) -> bool {
+        self.parser_version == PARSER_VERSION && self.size == size && self.modified == modified
+    }
+
+    /// Whether the file content is unchanged since it was processed by the current parser.
+    pub fn is_same_content(&self, size: u64, hash: u64) -> bool {
+        self.parser_version == PARSER_VERSION && self.size == size && self.hash == hash
+    }
+}
+
+pub fn modified_secs(metadata: &Metadata) -> i64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+/// 64-bit FNV-1a. Only used to notice changed files, not for security.
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
@@ -0,0 +1,163 @@
+//! How a player does against each opponent and against tables of different strength.
+
+use std::collections::HashMap;
+
+use maj_analyser::kyoku::KyokuRecord;
+use serde::Serialize;
+use sqlite::State;
+
+use crate::profile::PlayerProfile;
+use crate::query::GameFilter;
+use crate::{LogStore, Result};
+
+/// Width of the table average rate bands.
+pub const RATE_BAND_WIDTH: i32 = 100;
+
+/// 与某一对手同桌的对局
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct OpponentStats {
+    pub name: String,
+    /// 同桌场数
+    pub games: u32,
+    /// 顺位在对手之上的场数
+    pub above: u32,
+    /// 自己的顺位之和, 一位为 1
+    pub rank_sum: u32,
+    /// 自己在这些对局中的点数收支之和, 即终局点数减去开局点数
+    pub total_delta: i64,
+    /// 放铳给对手的次数
+    pub dealt_in_to: u32,
+    /// 放铳给对手的点数
+    pub dealt_in_to_score: i64,
+    /// 对手放铳给自己的次数
+    pub won_from: u32,
+    /// 对手放铳给自己的点数
+    pub won_from_score: i64,
+}
+
+/// 桌平均 R 在某一区间的对局
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RateBand {
+    /// 区间下限, 区间为 `[min, min + RATE_BAND_WIDTH)`
+    pub min: i32,
+    pub games: u32,
+    /// 各顺位的次数
+    pub ranks: [u32; 4],
+    /// 点数收支之和
+    pub total_delta: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct OpponentIndex {
+    /// 按同桌场数从多到少
+    pub opponents: Vec<OpponentStats>,
+    /// 按桌平均 R 从低到高
+    pub rate_bands: Vec<RateBand>,
+}
+
+impl LogStore {
+    /// Every opponent of `profile` in the games matching `filter`, and its results by
+    /// the average rate of the four players at the table.
+    pub fn opponent_index(
+        &self,
+        profile: &PlayerProfile,
+        filter: &GameFilter,
+    ) -> Result<OpponentIndex> {
+        let mut players = self.conn.prepare(
+            "SELECT name, rate, rank, score FROM players WHERE game_id = ? ORDER BY seat;",
+        )?;
+        let mut first_kyoku = self.conn.prepare(
+            "SELECT record FROM kyoku_records WHERE game_id = ? AND seat = ? ORDER BY idx LIMIT 1;",
+        )?;
+        let mut results = self
+            .conn
+            .prepare("SELECT winner, loser, score FROM kyoku_results WHERE game_id = ?;")?;
+        let mut opponents: HashMap<String, OpponentStats> = HashMap::new();
+        let mut bands: HashMap<i32, RateBand> = HashMap::new();
+        for game in self.profile_games(profile, filter)? {
+            let me = game.seat as usize;
+            players.reset()?;
+            players.bind((1, game.game_id.as_str()))?;
+            let mut table = vec![];
+            while let State::Row = players.next()? {
+                table.push((
+                    players.read::<String, _>(0)?,
+                    players.read::<f64, _>(1)?,
+                    players.read::<i64, _>(2)? as usize,
+                    players.read::<i64, _>(3)?,
+                ));
+            }
+            if table.len() != 4 {
+                continue;
+            }
+
+            // Final score against the starting one, so riichi sticks nobody collected count too.
+            first_kyoku.reset()?;
+            first_kyoku.bind((1, game.game_id.as_str()))?;
+            first_kyoku.bind((2, game.seat as i64))?;
+            let delta = match first_kyoku.next()? {
+                State::Row => {
+                    let record: KyokuRecord =
+                        serde_json::from_str(&first_kyoku.read::<String, _>(0)?)?;
+                    table[me].3 - record.score_before as i64
+                }
+                State::Done => 0,
+            };
+            let mut deal_ins = vec![];
+            results.reset()?;
+            results.bind((1, game.game_id.as_str()))?;
+            while let State::Row = results.next()? {
+                let winner = results.read::<Option<i64>, _>(0)?.map(|x| x as usize);
+                let loser = results.read::<Option<i64>, _>(1)?.map(|x| x as usize);
+                let score = results.read::<i64, _>(2)?;
+                if let (Some(winner), Some(loser)) = (winner, loser) {
+                    deal_ins.push((winner, loser, score));
+                }
+            }
+
+            let my_rank = table[me].2;
+            for (seat, (name, _, rank, _)) in table.iter().enumerate() {
+                if seat == me {
+                    continue;
+                }
+                let stats = opponents.entry(name.clone()).or_insert_with(|| OpponentStats {
+                    name: name.clone(),
+                    ..Default::default()
+                });
+                stats.games += 1;
+                stats.above += (my_rank < *rank) as u32;
+                stats.rank_sum += my_rank as u32 + 1;
+                stats.total_delta += delta;
+                for &(winner, loser, score) in deal_ins.iter() {
+                    if winner == seat && loser == me {
+                        stats.dealt_in_to += 1;
+                        stats.dealt_in_to_score += score;
+                    } else if winner == me && loser == seat {
+                        stats.won_from += 1;
+                        stats.won_from_score += score;
+                    }
+                }
+            }
+
+            let average = table.iter().map(|(_, rate, _, _)| rate).sum::<f64>() / 4.0;
+            let min = (average as i32).div_euclid(RATE_BAND_WIDTH) * RATE_BAND_WIDTH;
+            let band = bands.entry(min).or_insert_with(|| RateBand {
+                min,
+                ..Default::default()
+            });
+            band.games += 1;
+            band.ranks[my_rank.min(3)] += 1;
+            band.total_delta += delta;
+        }
+
+        let mut index = OpponentIndex {
+            opponents: opponents.into_values().collect(),
+            rate_bands: bands.into_values().collect(),
+        };
+        index
+            .opponents
+            .sort_by(|a, b| b.games.cmp(&a.games).then_with(|| a.name.cmp(&b.name)));
+        index.rate_bands.sort_by_key(|band| band.min);
+        Ok(index)
+    }
+}
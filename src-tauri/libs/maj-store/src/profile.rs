@@ -0,0 +1,217 @@
+//! One person playing under several Tenhou names, counted as a single player.
+
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use maj_analyser::counter::Counter;
+use serde::{Deserialize, Serialize};
+use sqlite::{State, Statement};
+
+use crate::query::{parse_date, GameFilter};
+use crate::{LogStore, Result};
+
+/// A name of a profile, optionally only for the games in a date range,
+/// e.g. when a name was later taken over by someone else.
+///
+/// Parsed from `name` or `name@since..until`, either end of the range being optional.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Alias {
+    pub name: String,
+    /// 只有此时间及之后的对局算作该档案, 格式为 `YYYYMMDDHH` 或其前缀
+    pub since: Option<String>,
+    /// 只有此时间及之前的对局算作该档案, 格式同上
+    pub until: Option<String>,
+}
+
+impl FromStr for Alias {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (name, range) = match s.rsplit_once('@') {
+            Some((name, range)) if range.contains("..") => (name, Some(range)),
+            _ => (s, None),
+        };
+        if name.is_empty() {
+            return Err(format!("Missing name: {}", s));
+        }
+        let mut alias = Alias {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        if let Some((since, until)) = range.and_then(|r| r.split_once("..")) {
+            let date = |value: &str| (!value.is_empty()).then(|| parse_date(value)).transpose();
+            alias.since = date(since)?;
+            alias.until = date(until)?;
+        }
+        Ok(alias)
+    }
+}
+
+impl Display for Alias {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        if self.since.is_some() || self.until.is_some() {
+            write!(
+                f,
+                "@{}..{}",
+                self.since.as_deref().unwrap_or(""),
+                self.until.as_deref().unwrap_or("")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A player known under several names.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerProfile {
+    /// 档案名, 仅用于显示
+    pub name: String,
+    pub aliases: Vec<Alias>,
+    /// 同时计入链接中带有自己座位 (`tw`) 的对局, 不论当时的名字
+    #[serde(default)]
+    pub owner: bool,
+}
+
+impl From<&str> for PlayerProfile {
+    /// A profile of a single name.
+    fn from(name: &str) -> Self {
+        PlayerProfile {
+            name: name.to_string(),
+            aliases: vec![Alias {
+                name: name.to_string(),
+                ..Default::default()
+            }],
+            owner: false,
+        }
+    }
+}
+
+impl PlayerProfile {
+    /// The games whose links had the seat (`tw`) of the user, whatever their name.
+    pub fn owner() -> Self {
+        PlayerProfile {
+            owner: true,
+            ..Default::default()
+        }
+    }
+}
+
+/// 一个别名的统计
+#[derive(Debug, Clone, Serialize)]
+pub struct AliasStats {
+    pub alias: Alias,
+    pub counter: Counter,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileStats {
+    /// 全部别名的合计
+    pub total: Counter,
+    /// 各别名的统计, 与档案中的别名同序
+    pub aliases: Vec<AliasStats>,
+}
+
+/// 档案的一场对局
+pub(crate) struct ProfileGame {
+    pub game_id: String,
+    pub seat: u8,
+    /// 对局所属的别名在档案中的下标, 只因座位 (`tw`) 计入的对局为 `None`
+    pub alias: Option<usize>,
+    /// 这一场的统计, 未解析的 JSON
+    pub counter: String,
+}
+
+impl LogStore {
+    /// Sums the statistics of every alias of `profile` over the games matching `filter`.
+    pub fn profile_stats(
+        &self,
+        profile: &PlayerProfile,
+        filter: &GameFilter,
+    ) -> Result<ProfileStats> {
+        let mut stats = ProfileStats {
+            total: Counter::default(),
+            aliases: profile
+                .aliases
+                .iter()
+                .map(|alias| AliasStats {
+                    alias: alias.clone(),
+                    counter: Counter::default(),
+                })
+                .collect(),
+        };
+        for game in self.profile_games(profile, filter)? {
+            let counter: Counter = serde_json::from_str(&game.counter)?;
+            stats.total += counter.clone();
+            if let Some(i) = game.alias {
+                stats.aliases[i].counter += counter;
+            }
+        }
+        Ok(stats)
+    }
+
+    /// The games of `profile` matching `filter`, most recent first.
+    ///
+    /// A game matched by several aliases is taken once, for the first of them,
+    /// and `last` applies to the games of all aliases together.
+    /// With [`owner`](PlayerProfile::owner), the games of the owner seat follow the aliases.
+    pub(crate) fn profile_games(
+        &self,
+        profile: &PlayerProfile,
+        filter: &GameFilter,
+    ) -> Result<Vec<ProfileGame>> {
+        let query = |condition: &str| {
+            format!(
+                "SELECT g.played_at, g.id, p.seat, s.counter FROM players p
+                 JOIN games g ON g.id = p.game_id
+                 JOIN player_stats s ON s.game_id = p.game_id AND s.name = p.name
+                 WHERE {} AND {}
+                 ORDER BY g.played_at DESC, g.id DESC LIMIT ?3;",
+                filter.condition(),
+                condition
+            )
+        };
+        let mut games = vec![];
+        let mut seen = HashSet::new();
+        let mut read = |stmt: &mut Statement, alias: Option<usize>| -> Result<()> {
+            while let State::Row = stmt.next()? {
+                let played_at = stmt.read::<String, _>(0)?;
+                let game = ProfileGame {
+                    game_id: stmt.read::<String, _>(1)?,
+                    seat: stmt.read::<i64, _>(2)? as u8,
+                    alias,
+                    counter: stmt.read::<String, _>(3)?,
+                };
+                if seen.insert(game.game_id.clone()) {
+                    games.push((played_at, game));
+                }
+            }
+            Ok(())
+        };
+        for (i, alias) in profile.aliases.iter().enumerate() {
+            let mut stmt = self.conn.prepare(query(
+                "p.name = ?4
+                 AND (?5 IS NULL OR g.played_at >= ?5)
+                 AND (?6 IS NULL OR substr(g.played_at, 1, length(?6)) <= ?6)",
+            ))?;
+            filter.bind(&mut stmt)?;
+            stmt.bind((4, alias.name.as_str()))?;
+            stmt.bind((5, alias.since.as_deref()))?;
+            stmt.bind((6, alias.until.as_deref()))?;
+            read(&mut stmt, Some(i))?;
+        }
+        if profile.owner {
+            let mut stmt = self.conn.prepare(query(
+                "EXISTS (SELECT 1 FROM owners o WHERE o.game_id = p.game_id AND o.seat = p.seat)",
+            ))?;
+            filter.bind(&mut stmt)?;
+            read(&mut stmt, None)?;
+        }
+        games.sort_by(|a, b| (&b.0, &b.1.game_id).cmp(&(&a.0, &a.1.game_id)));
+        if let Some(last) = filter.last {
+            games.truncate(last as usize);
+        }
+        Ok(games.into_iter().map(|(_, game)| game).collect())
+    }
+}
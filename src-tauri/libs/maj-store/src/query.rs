@@ -0,0 +1,190 @@
+use std::str::FromStr;
+
+use maj_analyser::counter::Counter;
+use serde::Serialize;
+use sqlite::{State, Statement};
+
+use crate::{LogStore, Result};
+
+/// Restricts which games a query covers.
+///
+/// Parsed from `key=value` pairs separated by commas, e.g. `since=20240101,last=100`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GameFilter {
+    /// 只统计此时间及之后的对局, 格式为 `YYYYMMDDHH` 或其前缀
+    pub since: Option<String>,
+    /// 只统计此时间及之前的对局, 格式同上
+    pub until: Option<String>,
+    /// 只统计最近的若干场
+    pub last: Option<u32>,
+}
+
+impl FromStr for GameFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut filter = GameFilter::default();
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or(format!("Expected key=value, got {}", part))?;
+            match key.trim() {
+                "since" => filter.since = Some(parse_date(value)?),
+                "until" => filter.until = Some(parse_date(value)?),
+                "last" => {
+                    filter.last = Some(value.parse().map_err(|_| format!("Invalid count: {}", value))?)
+                }
+                key => return Err(format!("Unknown filter: {}", key)),
+            }
+        }
+        Ok(filter)
+    }
+}
+
+/// `YYYYMMDDHH` or a prefix of it, dashes allowed.
+pub(crate) fn parse_date(value: &str) -> std::result::Result<String, String> {
+    let date = value.replace('-', "");
+    if date.is_empty() || date.len() > 10 || !date.chars().all(|c| c.is_ascii_digit()) {
+        Err(format!("Invalid date: {}", value))
+    } else {
+        Ok(date)
+    }
+}
+
+impl GameFilter {
+    /// SQL condition on the `games` table aliased as `g`.
+    /// Parameters `?1` to `?3` are taken by [`bind`](Self::bind), `?3` being the row limit.
+    pub(crate) fn condition(&self) -> &'static str {
+        "(?1 IS NULL OR g.played_at >= ?1)
+         AND (?2 IS NULL OR substr(g.played_at, 1, length(?2)) <= ?2)"
+    }
+
+    pub(crate) fn bind(&self, stmt: &mut Statement) -> Result<()> {
+        stmt.bind((1, self.since.as_deref()))?;
+        stmt.bind((2, self.until.as_deref()))?;
+        stmt.bind((3, self.last.map_or(-1, |x| x as i64)))?;
+        Ok(())
+    }
+}
+
+/// 某玩家的一场对局
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayerGame {
+    pub game_id: String,
+    pub played_at: String,
+    pub seat: u8,
+    pub dan: u8,
+    pub rate: f32,
+    pub score: i32,
+    /// 顺位, 0 为一位
+    pub rank: u8,
+}
+
+impl LogStore {
+    /// Sums the per-game statistics of `name` over every stored game.
+    pub fn player_counter(&self, name: &str) -> Result<Counter> {
+        self.player_counter_filtered(name, &GameFilter::default())
+    }
+
+    /// Sums the per-game statistics of `name` over the games matching `filter`.
+    pub fn player_counter_filtered(&self, name: &str, filter: &GameFilter) -> Result<Counter> {
+        self.counter_where("s.name = ?4", Some(name), filter)
+    }
+
+    fn counter_where(
+        &self,
+        condition: &str,
+        name: Option<&str>,
+        filter: &GameFilter,
+    ) -> Result<Counter> {
+        let mut stmt = self.conn.prepare(format!(
+            "SELECT s.counter FROM player_stats s JOIN games g ON g.id = s.game_id
+             WHERE {} AND {}
+             ORDER BY g.played_at DESC, g.id DESC LIMIT ?3;",
+            filter.condition(),
+            condition
+        ))?;
+        filter.bind(&mut stmt)?;
+        if let Some(name) = name {
+            stmt.bind((4, name))?;
+        }
+        let mut total = Counter::default();
+        while let State::Row = stmt.next()? {
+            let counter: Counter = serde_json::from_str(&stmt.read::<String, _>(0)?)?;
+            total += counter;
+        }
+        Ok(total)
+    }
+
+    /// The games `name` played matching `filter`, most recent first.
+    pub fn player_games(&self, name: &str, filter: &GameFilter) -> Result<Vec<PlayerGame>> {
+        self.games_where("p.name = ?4", Some(name), filter)
+    }
+
+    /// Sums the statistics of the user over the games whose owner seat is known,
+    /// whatever names they played under.
+    pub fn owner_counter(&self, filter: &GameFilter) -> Result<Counter> {
+        self.counter_where(
+            "EXISTS (SELECT 1 FROM owners o JOIN players p ON p.game_id = o.game_id AND p.seat = o.seat
+                     WHERE o.game_id = s.game_id AND p.name = s.name)",
+            None,
+            filter,
+        )
+    }
+
+    /// The games whose owner seat is known matching `filter`, from the owner's seat.
+    pub fn owner_games(&self, filter: &GameFilter) -> Result<Vec<PlayerGame>> {
+        self.games_where(
+            "EXISTS (SELECT 1 FROM owners o WHERE o.game_id = p.game_id AND o.seat = p.seat)",
+            None,
+            filter,
+        )
+    }
+
+    fn games_where(
+        &self,
+        condition: &str,
+        name: Option<&str>,
+        filter: &GameFilter,
+    ) -> Result<Vec<PlayerGame>> {
+        let mut stmt = self.conn.prepare(format!(
+            "SELECT g.id, g.played_at, p.seat, p.dan, p.rate, p.score, p.rank
+             FROM players p JOIN games g ON g.id = p.game_id
+             WHERE {} AND {}
+             ORDER BY g.played_at DESC, g.id DESC LIMIT ?3;",
+            filter.condition(),
+            condition
+        ))?;
+        filter.bind(&mut stmt)?;
+        if let Some(name) = name {
+            stmt.bind((4, name))?;
+        }
+        let mut games = vec![];
+        while let State::Row = stmt.next()? {
+            games.push(PlayerGame {
+                game_id: stmt.read::<String, _>(0)?,
+                played_at: stmt.read::<String, _>(1)?,
+                seat: stmt.read::<i64, _>(2)? as u8,
+                dan: stmt.read::<i64, _>(3)? as u8,
+                rate: stmt.read::<f64, _>(4)? as f32,
+                score: stmt.read::<i64, _>(5)? as i32,
+                rank: stmt.read::<i64, _>(6)? as u8,
+            });
+        }
+        Ok(games)
+    }
+
+    /// The name the user played the most games under when owner seats are known,
+    /// otherwise the name that appears in the most stored games.
+    pub fn most_frequent_player(&self) -> Result<Option<String>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT p.name FROM players p
+             LEFT JOIN owners o ON o.game_id = p.game_id AND o.seat = p.seat
+             GROUP BY p.name ORDER BY COUNT(o.seat) DESC, COUNT(*) DESC LIMIT 1;",
+        )?;
+        Ok(match stmt.next()? {
+            State::Row => Some(stmt.read::<String, _>(0)?),
+            State::Done => None,
+        })
+    }
+}
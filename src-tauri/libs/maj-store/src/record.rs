@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+
+use maj_analyser::counter::Counter;
+use maj_analyser::game::Game;
+use maj_analyser::kyoku::KyokuRecord;
+use tenhou_parser::maj_event::MajEvent;
+
+#[derive(Debug, Clone)]
+pub struct PlayerRecord {
+    pub seat: u8,
+    pub name: String,
+    pub dan: u8,
+    pub rate: f32,
+    /// 终局点数
+    pub score: i32,
+    /// 顺位, 0 为一位
+    pub rank: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResultKind {
+    Agari,
+    Ryuukyoku,
+}
+
+impl ResultKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ResultKind::Agari => "agari",
+            ResultKind::Ryuukyoku => "ryuukyoku",
+        }
+    }
+}
+
+/// 一局的结果. 一炮双响时同一局会有两条记录.
+#[derive(Debug, Clone)]
+pub struct KyokuResult {
+    /// 场风, 0 为东场, 1 为南场, 2 为西场
+    pub round_wind: u8,
+    /// 局数, 1 到 4
+    pub kyoku: u8,
+    pub honba: u8,
+    pub kyotaku: u8,
+    pub oya: u8,
+    pub kind: ResultKind,
+    pub winner: Option<u8>,
+    pub loser: Option<u8>,
+    pub score: i32,
+    pub diff_scores: [i32; 4],
+}
+
+/// 一个牌谱文件中提取出的全部可入库的信息.
+#[derive(Debug, Clone, Default)]
+pub struct GameRecord {
+    pub id: String,
+    /// 牌谱 id 开头的 `YYYYMMDDHH`
+    pub played_at: String,
+    pub players: Vec<PlayerRecord>,
+    pub results: Vec<KyokuResult>,
+    /// 每个玩家的每一局, 按座位再按局的顺序
+    pub kyokus: Vec<KyokuRecord>,
+    /// 每个玩家在这一场中的统计
+    pub stats: HashMap<String, Counter>,
+}
+
+impl GameRecord {
+    /// Builds the record from the events of one log.
+    /// Logs that are not applicable (sanma, room games, ...) yield a record without players.
+    pub fn from_events(id: &str, events: Vec<MajEvent>) -> Self {
+        let mut record = GameRecord {
+            id: id.to_string(),
+            played_at: played_at(id),
+            ..Default::default()
+        };
+        let names = match events.iter().find(|e| e.is_un()) {
+            Some(MajEvent::UN { id, .. }) => id.clone(),
+            _ => return record,
+        };
+        let mut game = Game::new(names);
+        let mut scores = [0i32; 4];
+        let (mut round_wind, mut kyoku, mut honba, mut kyotaku, mut oya) =
+            (0u8, 0u8, 0u8, 0u8, 0u8);
+        for event in events {
+            match &event {
+                MajEvent::UN { id, dan, rate } => {
+                    record.players = (0..4)
+                        .map(|i| PlayerRecord {
+                            seat: i as u8,
+                            name: id[i].clone(),
+                            dan: dan[i],
+                            rate: rate[i],
+                            score: 0,
+                            rank: 0,
+                        })
+                        .collect();
+                }
+                MajEvent::Init {
+                    round_wind: w,
+                    kyoku: k,
+                    honba: h,
+                    kyotaku: kt,
+                    oya: o,
+                    scores: s,
+                    ..
+                } => {
+                    (round_wind, kyoku, honba, kyotaku, oya) = (*w, *k, *h, *kt, *o);
+                    scores = *s;
+                }
+                MajEvent::ReachAccepted { after_scores, .. } => {
+                    scores = *after_scores;
+                }
+                MajEvent::Agari {
+                    actor,
+                    fromwho,
+                    score,
+                    after_scores,
+                    diff_scores,
+                    ..
+                } => {
+                    scores = *after_scores;
+                    record.results.push(KyokuResult {
+                        round_wind,
+                        kyoku,
+                        honba,
+                        kyotaku,
+                        oya,
+                        kind: ResultKind::Agari,
+                        winner: Some(*actor),
+                        loser: if fromwho != actor { Some(*fromwho) } else { None },
+                        score: *score,
+                        diff_scores: *diff_scores,
+                    });
+                }
+                MajEvent::Ryuukyoku {
+                    after_scores,
+                    diff_scores,
+                    ..
+                } => {
+                    scores = *after_scores;
+                    record.results.push(KyokuResult {
+                        round_wind,
+                        kyoku,
+                        honba,
+                        kyotaku,
+                        oya,
+                        kind: ResultKind::Ryuukyoku,
+                        winner: None,
+                        loser: None,
+                        score: 0,
+                        diff_scores: *diff_scores,
+                    });
+                }
+                _ => {}
+            }
+            game.on_event(event);
+        }
+
+        let mut order = (0..4).collect::<Vec<usize>>();
+        order.sort_by(|&a, &b| scores[b].cmp(&scores[a]));
+        for (rank, &i) in order.iter().enumerate() {
+            if let Some(player) = record.players.get_mut(i) {
+                player.score = scores[i];
+                player.rank = rank as u8;
+            }
+        }
+        record.kyokus = std::mem::take(&mut game.kyoku_records);
+        record.kyokus.sort_by_key(|kyoku| kyoku.seat);
+        record.stats = game.into_counters();
+        record
+    }
+}
+
+fn played_at(id: &str) -> String {
+    id.chars().take(10).take_while(|c| c.is_ascii_digit()).collect()
+}
@@ -0,0 +1,107 @@
+//! Results broken down by the standings a round was played in: dealer or not,
+//! leading or trailing, and the all-last round that decides the placement.
+
+use maj_analyser::kyoku::{KyokuOutcome, KyokuRecord};
+use serde::Serialize;
+
+use crate::profile::PlayerProfile;
+use crate::query::GameFilter;
+use crate::{LogStore, Result};
+
+/// A lead or deficit of more than this is a clear one.
+pub const MANGAN: i32 = 8000;
+
+/// 某一情形下的局
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SituationRounds {
+    pub rounds: u32,
+    /// 和了次数
+    pub wins: u32,
+    /// 放铳次数
+    pub deal_ins: u32,
+    /// 点数收支之和
+    pub total_delta: i64,
+}
+
+impl SituationRounds {
+    pub fn add(&mut self, record: &KyokuRecord) {
+        self.rounds += 1;
+        self.wins += matches!(record.outcome, KyokuOutcome::Tsumo | KyokuOutcome::Ron) as u32;
+        self.deal_ins += (record.outcome == KyokuOutcome::DealIn) as u32;
+        self.total_delta += record.delta as i64;
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SituationStats {
+    /// 场数
+    pub games: u32,
+    /// 进入 all-last 时的顺位到 all-last 结束时顺位的场数, `[进入时][结束时]`,
+    /// 未打到 all-last 的对局不计
+    pub all_last: [[u32; 4]; 4],
+    /// 顺位在 all-last 中发生变化的场数
+    pub decided_in_all_last: u32,
+    /// 自己为庄家的局
+    pub dealer: SituationRounds,
+    /// 自己为闲家的局
+    pub non_dealer: SituationRounds,
+    /// 开局时领先其他家一个满贯以上的局
+    pub leading: SituationRounds,
+    /// 开局时落后其他家最高点数一个满贯以上的局
+    pub trailing: SituationRounds,
+    /// 其余的局
+    pub close: SituationRounds,
+    /// all-last 的局
+    pub all_last_rounds: SituationRounds,
+}
+
+impl SituationStats {
+    /// Adds the rounds of one game, in the order they were played.
+    ///
+    /// The all-last is South 4, or East 4 in east-only games, with all its repeats.
+    /// Games that end before it, e.g. by a bust, are left out of the all-last counts,
+    /// and the rounds of a West extension are not part of it.
+    pub fn add_game(&mut self, records: &[&KyokuRecord]) {
+        if records.is_empty() {
+            return;
+        }
+        self.games += 1;
+        for record in records {
+            if record.is_dealer() {
+                self.dealer.add(record);
+            } else {
+                self.non_dealer.add(record);
+            }
+            match record.lead {
+                lead if lead > MANGAN => self.leading.add(record),
+                lead if lead < -MANGAN => self.trailing.add(record),
+                _ => self.close.add(record),
+            }
+        }
+        let all_last = records.iter().filter(|r| r.all_last).collect::<Vec<_>>();
+        let (Some(first), Some(last)) = (all_last.first(), all_last.last()) else {
+            return;
+        };
+        all_last.iter().for_each(|r| self.all_last_rounds.add(r));
+        let before = first.rank_before.min(3) as usize;
+        let after = last.rank_after.min(3) as usize;
+        self.all_last[before][after] += 1;
+        self.decided_in_all_last += (before != after) as u32;
+    }
+}
+
+impl LogStore {
+    /// The results of `profile` by situation over the games matching `filter`.
+    pub fn situation_stats(
+        &self,
+        profile: &PlayerProfile,
+        filter: &GameFilter,
+    ) -> Result<SituationStats> {
+        let kyokus = self.kyoku_records(profile, filter)?;
+        let mut stats = SituationStats::default();
+        for game in kyokus.chunk_by(|a, b| a.game_id == b.game_id) {
+            stats.add_game(&game.iter().map(|k| &k.record).collect::<Vec<_>>());
+        }
+        Ok(stats)
+    }
+}
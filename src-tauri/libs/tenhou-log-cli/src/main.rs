@@ -0,0 +1,618 @@
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use maj_analyser::counter::Counter;
+use maj_analyser::parallel::par_map;
+use maj_store::opponents::RATE_BAND_WIDTH;
+use maj_store::profile::{Alias, PlayerProfile};
+use maj_store::query::GameFilter;
+use maj_store::{log_id, LogStore};
+use serde::Serialize;
+use tenhou_logs_downloader::links::{extract_log_refs, owner_seats};
+use tenhou_logs_downloader::offline;
+use tenhou_logs_downloader::{download_files_with, DownloadProgress, DownloaderConfig, FileStatus};
+use tenhou_parser::event_emitter::try_parse_file;
+
+/// Identifier of the app, naming its folder in the platform data dir.
+const APP_IDENTIFIER: &str = "link.rika.tenhoulogmanager";
+
+/// Manage and analyse Tenhou logs without the GUI.
+#[derive(Parser)]
+#[command(name = "tenhou-log", version)]
+struct Cli {
+    /// Folder holding the downloaded logs, the app's logs folder unless given
+    #[arg(long, global = true, default_value_os_t = default_logs_dir())]
+    logs: PathBuf,
+    /// Game database built from the logs folder. Kept apart from the app's own,
+    /// which also covers its library roots
+    #[arg(long, global = true, default_value_os_t = app_data_dir().join("cli-games.db"))]
+    db: PathBuf,
+    /// Print JSON instead of tables
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Scan the browsers' local storage for log ids (with `--json`, also the profile each came from)
+    Import,
+    /// Download logs, finding the ids in the arguments or in stdin (urls, text, CSV, ...)
+    Download {
+        ids: Vec<String>,
+        /// URL the log id is appended to
+        #[arg(long)]
+        endpoint: Option<String>,
+        /// Number of simultaneous downloads
+        #[arg(long)]
+        concurrency: Option<usize>,
+        /// Maximum requests per second
+        #[arg(long)]
+        rate: Option<f64>,
+        #[arg(long)]
+        user_agent: Option<String>,
+        /// Save the logs gzip compressed, as `<id>.xml.gz`
+        #[arg(long)]
+        compress: bool,
+    },
+    /// Copy local logs (`.mjlog` saved by the client, gzip or plain) into the logs folder
+    Add {
+        paths: Vec<PathBuf>,
+        /// Save the logs gzip compressed, as `<id>.xml.gz`
+        #[arg(long)]
+        compress: bool,
+    },
+    /// Print the ids of the games of a player listed in a `scc*.html(.gz)` index
+    Index {
+        path: PathBuf,
+        #[arg(long)]
+        player: String,
+    },
+    /// Print the statistics of a player
+    Stats {
+        #[arg(long, required_unless_present_any = ["owner", "aliases"])]
+        player: Option<String>,
+        /// The games whose links had the seat (`tw`) of the user, whatever their name
+        #[arg(long, conflicts_with = "player")]
+        owner: bool,
+        /// Count the games of several names together, each `name` or `name@since..until`
+        #[arg(long = "alias", conflicts_with_all = ["player", "owner"])]
+        aliases: Vec<Alias>,
+        /// e.g. `since=2024-01-01,until=2024-06-30,last=100`
+        #[arg(long, default_value = "")]
+        filter: GameFilter,
+    },
+    /// Print the results against every opponent and by the average rate of the table
+    Opponents {
+        #[arg(long, required_unless_present_any = ["owner", "aliases"])]
+        player: Option<String>,
+        /// Also count the games whose links had the seat (`tw`) of the user, whatever their name
+        #[arg(long, conflicts_with = "player")]
+        owner: bool,
+        /// Count the games of several names together, each `name` or `name@since..until`
+        #[arg(long = "alias", conflicts_with = "player")]
+        aliases: Vec<Alias>,
+        #[arg(long, default_value = "")]
+        filter: GameFilter,
+        /// Leave out opponents met in fewer games
+        #[arg(long, default_value_t = 1)]
+        min_games: u32,
+    },
+    /// List every round of a player with the standings before and after it
+    Kyokus {
+        #[arg(long, required_unless_present_any = ["owner", "aliases"])]
+        player: Option<String>,
+        /// Also count the games whose links had the seat (`tw`) of the user, whatever their name
+        #[arg(long, conflicts_with = "player")]
+        owner: bool,
+        /// Count the games of several names together, each `name` or `name@since..until`
+        #[arg(long = "alias", conflicts_with = "player")]
+        aliases: Vec<Alias>,
+        #[arg(long, default_value = "")]
+        filter: GameFilter,
+    },
+    /// Print the results of a player as dealer, leading, trailing and in the all-last
+    Situations {
+        #[arg(long, required_unless_present_any = ["owner", "aliases"])]
+        player: Option<String>,
+        /// Also count the games whose links had the seat (`tw`) of the user, whatever their name
+        #[arg(long, conflicts_with = "player")]
+        owner: bool,
+        /// Count the games of several names together, each `name` or `name@since..until`
+        #[arg(long = "alias", conflicts_with = "player")]
+        aliases: Vec<Alias>,
+        #[arg(long, default_value = "")]
+        filter: GameFilter,
+    },
+    /// Parse every log in the logs folder and report the ones that fail
+    Verify,
+    /// Export the games of a player
+    Export {
+        #[arg(long, required_unless_present = "owner")]
+        player: Option<String>,
+        #[arg(long, conflicts_with = "player")]
+        owner: bool,
+        #[arg(long, default_value = "")]
+        filter: GameFilter,
+        #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    Csv,
+    Json,
+}
+
+#[derive(Serialize)]
+struct Failure {
+    path: String,
+    error: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    match &cli.command {
+        Command::Import => {
+            let mut seen = BTreeSet::new();
+            let mut found = browser_ls_reader::read_profiles(&browser_ls_reader::discover());
+            found.retain(|log| seen.insert(log.id.clone()));
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&found)?);
+            } else {
+                seen.iter().for_each(|id| println!("{}", id));
+            }
+        }
+        Command::Download {
+            ids,
+            endpoint,
+            concurrency,
+            rate,
+            user_agent,
+            compress,
+        } => {
+            let text = if ids.is_empty() {
+                std::io::read_to_string(std::io::stdin())?
+            } else {
+                ids.join("\n")
+            };
+            let refs = extract_log_refs(&text);
+            open_store(&cli)?.set_owner_seats(&owner_seats(&refs))?;
+            let ids = refs.into_iter().map(|r| r.id).collect();
+            let default = DownloaderConfig::default();
+            let config = DownloaderConfig {
+                base_url: endpoint.clone().unwrap_or(default.base_url),
+                concurrency: concurrency.unwrap_or(default.concurrency),
+                requests_per_second: rate.or(default.requests_per_second),
+                user_agent: user_agent.clone().unwrap_or(default.user_agent),
+                compress: *compress,
+                ..default
+            };
+            let on_progress = |p: DownloadProgress| {
+                if let Some(error) = p.error {
+                    eprintln!("[{}/{}] {}: {}", p.done, p.total, p.current, error);
+                }
+            };
+            let report = download_files_with(
+                ids,
+                &cli.logs,
+                &config,
+                on_progress,
+                &AtomicBool::new(false),
+            )
+            .await?;
+            let summary = open_store(&cli)?.import_dir(&cli.logs)?;
+            if cli.json {
+                println!(
+                    "{}",
+                    serde_json::json!({
+                        "downloaded": report.downloaded,
+                        "skipped": report.skipped,
+                        "failed": report.failed,
+                        "imported": summary.imported,
+                        "files": report.files,
+                    })
+                );
+            } else {
+                print_table(&[
+                    ("Downloaded", report.downloaded.to_string()),
+                    ("Skipped", report.skipped.to_string()),
+                    ("Failed", report.failed.to_string()),
+                    ("Imported", summary.imported.to_string()),
+                ]);
+            }
+        }
+        Command::Add { paths, compress } => {
+            let names = paths
+                .iter()
+                .filter_map(|path| path.file_name())
+                .map(|name| name.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("\n");
+            open_store(&cli)?.set_owner_seats(&owner_seats(&extract_log_refs(&names)))?;
+            let report = offline::import_files(paths, &cli.logs, *compress)?;
+            for file in report.files.iter() {
+                if let FileStatus::Failed(reason) = &file.status {
+                    eprintln!("{}: {}", file.url, reason);
+                }
+            }
+            let summary = open_store(&cli)?.import_dir(&cli.logs)?;
+            if cli.json {
+                println!(
+                    "{}",
+                    serde_json::json!({
+                        "added": report.downloaded,
+                        "skipped": report.skipped,
+                        "failed": report.failed,
+                        "imported": summary.imported,
+                        "files": report.files,
+                    })
+                );
+            } else {
+                print_table(&[
+                    ("Added", report.downloaded.to_string()),
+                    ("Skipped", report.skipped.to_string()),
+                    ("Failed", report.failed.to_string()),
+                    ("Imported", summary.imported.to_string()),
+                ]);
+            }
+        }
+        Command::Index { path, player } => {
+            let ids = offline::index_ids(path, player)?;
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&ids)?);
+            } else {
+                ids.iter().for_each(|id| println!("{}", id));
+            }
+        }
+        Command::Stats {
+            aliases, filter, ..
+        } if !aliases.is_empty() => {
+            let store = open_store(&cli)?;
+            store.import_dir(&cli.logs)?;
+            let stats = store.profile_stats(&profile_of(&None, aliases, false), filter)?;
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+            } else {
+                print_table(&stats_table(&stats.total));
+                println!();
+                let names = stats
+                    .aliases
+                    .iter()
+                    .map(|a| a.alias.to_string())
+                    .collect::<Vec<_>>();
+                let rows = names
+                    .iter()
+                    .zip(stats.aliases.iter())
+                    .map(|(name, a)| (name.as_str(), format!("{} games", a.counter.matches)))
+                    .collect::<Vec<_>>();
+                print_table(&rows);
+            }
+        }
+        Command::Stats {
+            player,
+            owner,
+            filter,
+            ..
+        } => {
+            let store = open_store(&cli)?;
+            store.import_dir(&cli.logs)?;
+            let counter = match player {
+                Some(player) if !owner => store.player_counter_filtered(player, filter)?,
+                _ => store.owner_counter(filter)?,
+            };
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&counter)?);
+            } else {
+                print_table(&stats_table(&counter));
+            }
+        }
+        Command::Opponents {
+            player,
+            owner,
+            aliases,
+            filter,
+            min_games,
+        } => {
+            let store = open_store(&cli)?;
+            store.import_dir(&cli.logs)?;
+            let profile = profile_of(player, aliases, *owner);
+            let mut index = store.opponent_index(&profile, filter)?;
+            index.opponents.retain(|o| o.games >= *min_games);
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&index)?);
+            } else {
+                println!("opponent,games,above,avg_rank,avg_delta,dealt_in_to,won_from");
+                for o in index.opponents.iter() {
+                    println!(
+                        "{},{},{},{:.2},{},{},{}",
+                        o.name,
+                        o.games,
+                        percent(o.above, o.games),
+                        o.rank_sum as f32 / o.games as f32,
+                        o.total_delta / o.games as i64,
+                        o.dealt_in_to,
+                        o.won_from
+                    );
+                }
+                println!();
+                println!("table_rate,games,1st,2nd,3rd,4th,avg_delta");
+                for band in index.rate_bands.iter() {
+                    println!(
+                        "{}-{},{},{},{},{},{},{}",
+                        band.min,
+                        band.min + RATE_BAND_WIDTH,
+                        band.games,
+                        percent(band.ranks[0], band.games),
+                        percent(band.ranks[1], band.games),
+                        percent(band.ranks[2], band.games),
+                        percent(band.ranks[3], band.games),
+                        band.total_delta / band.games as i64
+                    );
+                }
+            }
+        }
+        Command::Kyokus {
+            player,
+            owner,
+            aliases,
+            filter,
+        } => {
+            let store = open_store(&cli)?;
+            store.import_dir(&cli.logs)?;
+            let kyokus = store.kyoku_records(&profile_of(player, aliases, *owner), filter)?;
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&kyokus)?);
+            } else {
+                println!("game,round,honba,seat_wind,score,rank,riichi,called,outcome,delta,rank_after");
+                for k in kyokus.iter() {
+                    let r = &k.record;
+                    println!(
+                        "{},{}{},{},{},{},{},{},{},{},{},{}",
+                        k.game_id,
+                        ["E", "S", "W", "N"][r.round_wind as usize % 4],
+                        r.kyoku,
+                        r.honba,
+                        ["E", "S", "W", "N"][r.seat_wind as usize],
+                        r.score_before,
+                        r.rank_before + 1,
+                        r.riichi,
+                        r.called,
+                        r.outcome.as_str(),
+                        r.delta,
+                        r.rank_after + 1
+                    );
+                }
+            }
+        }
+        Command::Situations {
+            player,
+            owner,
+            aliases,
+            filter,
+        } => {
+            let store = open_store(&cli)?;
+            store.import_dir(&cli.logs)?;
+            let stats = store.situation_stats(&profile_of(player, aliases, *owner), filter)?;
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+            } else {
+                println!("situation,rounds,win,deal_in,avg_delta");
+                for (name, rounds) in [
+                    ("dealer", &stats.dealer),
+                    ("non_dealer", &stats.non_dealer),
+                    ("leading", &stats.leading),
+                    ("trailing", &stats.trailing),
+                    ("close", &stats.close),
+                    ("all_last", &stats.all_last_rounds),
+                ] {
+                    println!(
+                        "{},{},{},{},{}",
+                        name,
+                        rounds.rounds,
+                        percent(rounds.wins, rounds.rounds),
+                        percent(rounds.deal_ins, rounds.rounds),
+                        rounds.total_delta / rounds.rounds.max(1) as i64
+                    );
+                }
+                println!();
+                println!("all_last_rank,games,1st,2nd,3rd,4th");
+                for (rank, finals) in stats.all_last.iter().enumerate() {
+                    let games = finals.iter().sum();
+                    println!(
+                        "{},{},{},{},{},{}",
+                        rank + 1,
+                        games,
+                        percent(finals[0], games),
+                        percent(finals[1], games),
+                        percent(finals[2], games),
+                        percent(finals[3], games)
+                    );
+                }
+                println!();
+                println!(
+                    "Decided in all-last: {}",
+                    percent(stats.decided_in_all_last, stats.all_last.iter().flatten().sum())
+                );
+            }
+        }
+        Command::Verify => {
+            let paths = log_files(&cli.logs)?;
+            let failures = paths
+                .iter()
+                .zip(par_map(&paths, |path| try_parse_file(path)))
+                .filter_map(|(path, result)| {
+                    result.err().map(|error| Failure {
+                        path: path.to_string_lossy().to_string(),
+                        error,
+                    })
+                })
+                .collect::<Vec<_>>();
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&failures)?);
+            } else {
+                failures
+                    .iter()
+                    .for_each(|f| println!("{}: {}", f.path, f.error));
+                println!("{} files, {} failed", paths.len(), failures.len());
+            }
+            if !failures.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Command::Export {
+            player,
+            owner,
+            filter,
+            format,
+        } => {
+            let store = open_store(&cli)?;
+            store.import_dir(&cli.logs)?;
+            let games = match player {
+                Some(player) if !owner => store.player_games(player, filter)?,
+                _ => store.owner_games(filter)?,
+            };
+            match format {
+                ExportFormat::Json => println!("{}", serde_json::to_string_pretty(&games)?),
+                ExportFormat::Csv => {
+                    println!("game_id,played_at,seat,dan,rate,score,rank");
+                    for g in games {
+                        println!(
+                            "{},{},{},{},{:.2},{},{}",
+                            g.game_id,
+                            g.played_at,
+                            g.seat,
+                            g.dan,
+                            g.rate,
+                            g.score,
+                            g.rank + 1
+                        );
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The folder the app keeps its settings and database in.
+fn app_data_dir() -> PathBuf {
+    dirs::data_dir().unwrap_or_default().join(APP_IDENTIFIER)
+}
+
+/// The logs folder set in the app's settings, or its default.
+fn default_logs_dir() -> PathBuf {
+    std::fs::read_to_string(app_data_dir().join("settings.json"))
+        .ok()
+        .and_then(|s| serde_json::from_str::<serde_json::Value>(&s).ok())
+        .and_then(|settings| {
+            let dir = settings.get("logsDir")?.as_str()?;
+            (!dir.is_empty()).then(|| PathBuf::from(dir))
+        })
+        .unwrap_or_else(|| app_data_dir().join("logs"))
+}
+
+fn open_store(cli: &Cli) -> Result<LogStore, Box<dyn Error>> {
+    if let Some(parent) = cli.db.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    Ok(LogStore::open(&cli.db)?)
+}
+
+fn log_files(dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut paths = dir
+        .read_dir()?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.retain(|path| path.is_file() && log_id(path).is_some());
+    paths.sort();
+    Ok(paths)
+}
+
+/// The profile of `player`, or of `aliases` when no player is given.
+fn profile_of(player: &Option<String>, aliases: &[Alias], owner: bool) -> PlayerProfile {
+    match player {
+        Some(player) => PlayerProfile::from(player.as_str()),
+        None => PlayerProfile {
+            name: String::new(),
+            aliases: aliases.to_vec(),
+            owner,
+        },
+    }
+}
+
+fn percent(x: u32, total: u32) -> String {
+    if total == 0 {
+        "-".to_string()
+    } else {
+        format!("{:.2}%", x as f32 / total as f32 * 100.0)
+    }
+}
+
+fn stats_table(c: &Counter) -> Vec<(&'static str, String)> {
+    vec![
+        ("Games", c.matches.to_string()),
+        ("Rounds", c.rounds.to_string()),
+        ("1st", percent(c.rank1, c.matches)),
+        ("2nd", percent(c.rank2, c.matches)),
+        ("3rd", percent(c.rank3, c.matches)),
+        ("4th", percent(c.rank4, c.matches)),
+        ("Busted", percent(c.tobi, c.matches)),
+        ("Win", percent(c.wins, c.rounds)),
+        ("Deal-in", percent(c.loses, c.rounds)),
+        ("Deal-in not tenpai", percent(c.deal_in.not_tenpai, c.deal_in.count)),
+        ("Deal-in to riichi", percent(c.deal_in.to_riichi, c.deal_in.count)),
+        ("Deal-in unread 2-8", percent(c.deal_in.unread_middle, c.deal_in.count)),
+        ("Deal-in with safer tile", percent(c.deal_in.safer_in_hand, c.deal_in.count)),
+        ("Push vs riichi", percent(c.push_fold.pushes, c.push_fold.turns)),
+        (
+            "Push at 2-shanten",
+            percent(c.push_fold.push_two_shanten, c.push_fold.pushes),
+        ),
+        (
+            "Fold tenpai mangan",
+            percent(c.push_fold.fold_tenpai_mangan, c.push_fold.tenpai_mangan),
+        ),
+        ("Tsumo", percent(c.win_tsumo, c.wins)),
+        ("Riichi", percent(c.riichi, c.rounds)),
+        ("Call", percent(c.total_furo, c.rounds)),
+        ("Draw", percent(c.draw, c.rounds)),
+        (
+            "Avg. win",
+            if c.wins == 0 {
+                "-".to_string()
+            } else {
+                (c.win_score / c.wins as i64).to_string()
+            },
+        ),
+        (
+            "Avg. deal-in",
+            if c.loses == 0 {
+                "-".to_string()
+            } else {
+                (c.lose_score / c.loses as i64).to_string()
+            },
+        ),
+        (
+            "Stable rate",
+            if c.matches == 0 {
+                "-".to_string()
+            } else {
+                format!("{:.0}", c.tot_rate / c.matches as f32 * 40.0)
+            },
+        ),
+    ]
+}
+
+fn print_table(rows: &[(&str, String)]) {
+    let width = rows.iter().map(|(k, _)| k.len()).max().unwrap_or(0);
+    for (k, v) in rows {
+        println!("{:<width$}  {:>10}", k, v, width = width);
+    }
+}
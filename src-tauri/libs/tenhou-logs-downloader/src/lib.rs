@@ -1,95 +1,485 @@
-use std::path::PathBuf;
-use std::sync::Arc;
+pub mod links;
+pub mod offline;
+
+use std::fmt::{Debug, Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+use std::io::Write;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use futures::future::join_all;
-use reqwest::Client;
+use reqwest::{Client, StatusCode};
+use serde::Serialize;
+use tenhou_parser::event_emitter::try_parse_bytes;
 use tokio::fs;
-use tokio::io::AsyncWriteExt;
-use tokio::sync::Semaphore;
+use tokio::sync::{Mutex, Semaphore};
+use tokio::time::Instant;
+
+#[derive(Debug, Clone)]
+pub struct DownloaderConfig {
+    /// 牌谱 id 直接拼接在后面
+    pub base_url: String,
+    /// 同时进行的下载数
+    pub concurrency: usize,
+    /// 每秒最多发出的请求数 (含重试), `None` 为不限
+    pub requests_per_second: Option<f64>,
+    pub user_agent: String,
+    /// 每个文件最多尝试的次数, 含第一次
+    pub max_attempts: u32,
+    /// 第一次重试前的等待时间, 之后每次翻倍
+    pub retry_delay: Duration,
+    /// 以 gzip 压缩保存为 `<id>.xml.gz`
+    pub compress: bool,
+}
+
+impl Default for DownloaderConfig {
+    fn default() -> Self {
+        DownloaderConfig {
+            base_url: "https://tenhou.net/0/log/?".to_string(),
+            concurrency: 5,
+            requests_per_second: None,
+            user_agent: concat!("tenhou-log-manager/", env!("CARGO_PKG_VERSION")).to_string(),
+            max_attempts: 4,
+            retry_delay: Duration::from_millis(500),
+            compress: false,
+        }
+    }
+}
+
+impl DownloaderConfig {
+    pub fn log_url(&self, id: &str) -> String {
+        format!("{}{}", self.base_url, id)
+    }
+
+    pub fn file_name(&self, id: &str) -> String {
+        if self.compress {
+            format!("{}.xml.gz", id)
+        } else {
+            format!("{}.xml", id)
+        }
+    }
+}
 
+/// Spaces requests to the endpoint at least `interval` apart.
+struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    fn new(requests_per_second: Option<f64>) -> Self {
+        RateLimiter {
+            interval: requests_per_second
+                .filter(|r| *r > 0.0)
+                .map_or(Duration::ZERO, |r| Duration::from_secs_f64(1.0 / r)),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    async fn wait(&self) {
+        if self.interval.is_zero() {
+            return;
+        }
+        let at = {
+            let mut next = self.next.lock().await;
+            let at = (*next).max(Instant::now());
+            *next = at + self.interval;
+            at
+        };
+        tokio::time::sleep_until(at).await;
+    }
+}
+
+pub enum DownloadError {
+    Http(reqwest::Error),
+    Status(StatusCode),
+    /// 响应不是牌谱, 例如 id 不存在时返回的页面
+    InvalidContent,
+    /// 响应是牌谱但无法解析
+    Parse(String),
+    Io(std::io::Error),
+    Cancelled,
+}
+
+impl DownloadError {
+    /// Network errors, rate limiting and server errors are worth another attempt.
+    fn is_retryable(&self) -> bool {
+        match self {
+            DownloadError::Http(_) => true,
+            DownloadError::Status(status) => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            _ => false,
+        }
+    }
+}
+
+impl From<reqwest::Error> for DownloadError {
+    fn from(e: reqwest::Error) -> Self {
+        DownloadError::Http(e)
+    }
+}
+
+impl From<std::io::Error> for DownloadError {
+    fn from(e: std::io::Error) -> Self {
+        DownloadError::Io(e)
+    }
+}
+
+impl Debug for DownloadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DownloadError::Http(e) => write!(f, "HttpError: {}", e),
+            DownloadError::Status(status) => write!(f, "StatusError: {}", status),
+            DownloadError::InvalidContent => write!(f, "InvalidContent: response is not a mjlog"),
+            DownloadError::Parse(e) => write!(f, "ParseError: {}", e),
+            DownloadError::Io(e) => write!(f, "IoError: {}", e),
+            DownloadError::Cancelled => write!(f, "Cancelled"),
+        }
+    }
+}
+
+impl Display for DownloadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for DownloadError {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", content = "reason", rename_all = "camelCase")]
+pub enum FileStatus {
+    Downloaded,
+    /// 文件已存在且非空
+    Skipped,
+    Failed(String),
+    /// 取消时尚未开始下载
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileResult {
+    pub id: String,
+    pub url: String,
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub status: FileStatus,
+}
+
+/// Outcome of a [`download_files`] batch, in the order of the given ids.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DownloadReport {
+    pub downloaded: u64,
+    pub skipped: u64,
+    pub failed: u64,
+    pub cancelled: u64,
+    pub files: Vec<FileResult>,
+}
+
+impl DownloadReport {
+    pub(crate) fn new(files: Vec<FileResult>) -> Self {
+        let mut report = DownloadReport::default();
+        for file in files.iter() {
+            match file.status {
+                FileStatus::Downloaded => report.downloaded += 1,
+                FileStatus::Skipped => report.skipped += 1,
+                FileStatus::Failed(_) => report.failed += 1,
+                FileStatus::Cancelled => report.cancelled += 1,
+            }
+        }
+        report.files = files;
+        report
+    }
+}
+
+/// Reported once for every finished file of a [`download_files_with`] batch.
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadProgress {
+    pub done: u64,
+    pub total: u64,
+    /// 刚处理完的牌谱 id
+    pub current: String,
+    /// 下载失败时的错误信息
+    pub error: Option<String>,
+}
+
+/// Whether `save_dir` already holds a non-empty `<id>.xml` or `<id>.xml.gz`.
+pub fn is_saved(save_dir: &Path, id: &str) -> bool {
+    [format!("{}.xml", id), format!("{}.xml.gz", id)]
+        .iter()
+        .any(|name| save_dir.join(name).metadata().is_ok_and(|m| m.len() > 0))
+}
+
+/// Writes `content` next to `path` first and then renames it into place,
+/// so an interrupted write never leaves a partial log.
+fn write_atomic(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    std::fs::write(&part, content)?;
+    std::fs::rename(&part, path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&part);
+    })
+}
+
+fn gzip(content: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder.write_all(content)?;
+    encoder.finish()
+}
+
+/// Whether `content` looks like a mjlog rather than an error page.
+pub fn is_mjlog(content: &[u8]) -> bool {
+    content.trim_ascii_start().starts_with(b"<mjloggm")
+}
+
+/// Checks that `content` is a mjlog the parser can read, before it is saved.
+fn validate(content: &[u8]) -> Result<(), DownloadError> {
+    if !is_mjlog(content) {
+        return Err(DownloadError::InvalidContent);
+    }
+    try_parse_bytes(content).map_err(DownloadError::Parse)?;
+    Ok(())
+}
+
+async fn fetch(client: &Client, url: &str) -> Result<Vec<u8>, DownloadError> {
+    let response = client.get(url).send().await?;
+    if !response.status().is_success() {
+        return Err(DownloadError::Status(response.status()));
+    }
+    let content = response.bytes().await?;
+    validate(&content)?;
+    Ok(content.to_vec())
+}
+
+/// Fetches `url`, retrying with exponential backoff, and saves it to `path` once complete.
 async fn download_file(
     client: &Client,
+    config: &DownloaderConfig,
+    limiter: &RateLimiter,
     url: &str,
-    path: PathBuf,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let response = client.get(url).send().await?;
-    let mut file = fs::File::create(path).await?;
-    let content = response.bytes().await?;
-    file.write_all(&content).await?;
+    path: &Path,
+) -> Result<(), DownloadError> {
+    let mut delay = config.retry_delay;
+    let mut attempt = 1;
+    let content = loop {
+        limiter.wait().await;
+        match fetch(client, url).await {
+            Ok(content) => break content,
+            Err(e) if e.is_retryable() && attempt < config.max_attempts => {
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    };
+    // Compressing and writing block, so keep them off the async workers.
+    let (compress, path) = (config.compress, path.to_owned());
+    tokio::task::spawn_blocking(move || {
+        let content = if compress { gzip(&content)? } else { content };
+        write_atomic(&path, &content)
+    })
+    .await
+    .map_err(std::io::Error::other)??;
     Ok(())
 }
 
-async fn check_and_download(
-    client: Arc<Client>,
-    url: String,
-    path: PathBuf,
-    semaphore: Arc<Semaphore>,
-) -> Result<bool, ()> {
-    let _permit = semaphore.acquire().await;
-
-    if path.exists() {
-        let metadata = fs::metadata(&path).await.map_err(|_| ())?;
-        if metadata.len() > 0 {
-            println!(
-                "File already exists and is not empty, skipping download: {}",
-                path.to_str().unwrap()
-            );
+struct Downloader<'a> {
+    client: Client,
+    config: &'a DownloaderConfig,
+    limiter: RateLimiter,
+    semaphore: Semaphore,
+    cancel: &'a AtomicBool,
+}
+
+impl Downloader<'_> {
+    async fn check_and_download(&self, id: &str, url: &str, path: &Path) -> Result<bool, DownloadError> {
+        let _permit = self.semaphore.acquire().await;
+        if self.cancel.load(Ordering::Relaxed) {
+            return Err(DownloadError::Cancelled);
+        }
+
+        if path.parent().is_some_and(|dir| is_saved(dir, id)) {
             return Ok(false);
         }
-    }
 
-    println!("Downloading: {}", url);
-    download_file(&client, &url, path).await.map_err(|e| eprintln!("Error: {}", e))?;
-    println!("Downloaded: {}", url);
+        download_file(&self.client, self.config, &self.limiter, url, path).await?;
+        Ok(true)
+    }
+}
 
-    Ok(true)
+/// Downloads the logs with the given ids into `save_dir` as `<id>.xml`, with the default config.
+/// Logs already saved, compressed or not, are skipped.
+pub async fn download_files(ids: Vec<String>, save_dir: &PathBuf) -> Result<DownloadReport, String> {
+    download_files_with(ids, save_dir, &DownloaderConfig::default(), |_| {}, &AtomicBool::new(false)).await
 }
 
-pub async fn download_files(urls: Vec<String>, save_dir: &PathBuf) -> Result<(u64, u64, u64), String> {
+/// Like [`download_files`], calling `on_progress` after each file.
+/// Setting `cancel` stops the batch: files not started yet are reported as cancelled.
+pub async fn download_files_with(
+    ids: Vec<String>,
+    save_dir: &PathBuf,
+    config: &DownloaderConfig,
+    on_progress: impl Fn(DownloadProgress) + Send + Sync,
+    cancel: &AtomicBool,
+) -> Result<DownloadReport, String> {
     fs::create_dir_all(save_dir).await.map_err(|e| e.to_string())?;
-    let client = Arc::new(Client::new());
-    let semaphore = Arc::new(Semaphore::new(5));
-    let (mut skipped, mut downloaded, mut fail) = (0u64, 0u64, 0u64);
-
-    let futures = urls.into_iter().map(|url| {
-        let client = Arc::clone(&client);
-        let semaphore = Arc::clone(&semaphore);
-        let path = save_dir.join(
-            PathBuf::from(&url)
-                .with_extension("xml")
-                .file_name()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .replace("?", ""),
-        );
-        check_and_download(client, url, path, semaphore)
-    });
-
-    let results = join_all(futures).await;
+    let downloader = Downloader {
+        client: Client::builder()
+            .user_agent(&config.user_agent)
+            .build()
+            .map_err(|e| e.to_string())?,
+        config,
+        limiter: RateLimiter::new(config.requests_per_second),
+        semaphore: Semaphore::new(config.concurrency.max(1)),
+        cancel,
+    };
+    let total = ids.len() as u64;
+    let done = AtomicU64::new(0);
 
-    for result in results {
-        match result {
-            Ok(true) => downloaded += 1,
-            Ok(false) => skipped += 1,
-            Err(_) => {
-                fail += 1;
+    let futures = ids.into_iter().map(|id| {
+        let url = config.log_url(&id);
+        let path = save_dir.join(config.file_name(&id));
+        let (downloader, on_progress, done) = (&downloader, &on_progress, &done);
+        async move {
+            let status = match downloader.check_and_download(&id, &url, &path).await {
+                Ok(true) => FileStatus::Downloaded,
+                Ok(false) => FileStatus::Skipped,
+                Err(DownloadError::Cancelled) => FileStatus::Cancelled,
+                Err(e) => FileStatus::Failed(e.to_string()),
+            };
+            if status != FileStatus::Cancelled {
+                on_progress(DownloadProgress {
+                    done: done.fetch_add(1, Ordering::Relaxed) + 1,
+                    total,
+                    current: id.clone(),
+                    error: match &status {
+                        FileStatus::Failed(reason) => Some(reason.clone()),
+                        _ => None,
+                    },
+                });
             }
+            FileResult { id, url, path, status }
         }
-    }
+    });
 
-    Ok((downloaded, skipped, fail))
+    Ok(DownloadReport::new(join_all(futures).await))
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
     use super::*;
 
     #[tokio::test]
     async fn it_works() {
-        let urls = "2024072317gm-0089-0000-87c78500,2024072217gm-0089-0000-d5b489ec,2024072017gm-0089-0000-1fdd9250,2024071919gm-0009-0000-adcf8a34,2024071817gm-0009-0000-1d267e0f"
-            .split(',').map(|s| "https://tenhou.net/0/log/?".to_string() + s).collect();
+        let ids = "2024072317gm-0089-0000-87c78500,2024072217gm-0089-0000-d5b489ec,2024072017gm-0089-0000-1fdd9250,2024071919gm-0009-0000-adcf8a34,2024071817gm-0009-0000-1d267e0f"
+            .split(',').map(|s| s.to_string()).collect();
         let save_dir = PathBuf::from(r#"D:\Projects\tenhou-log-manager\src-tauri\logs"#);
 
-        download_files(urls, &save_dir).await.expect("Failed to download files");
+        download_files(ids, &save_dir).await.expect("Failed to download files");
+    }
+
+    const LOG: &str = "<mjloggm ver=\"2.3\"></mjloggm>";
+
+    /// Serves `?<id>` requests on a local port: `ok` ids get a log, `flaky` ids fail
+    /// with 503 on their first request, `html` ids get a page, `broken` ids a log that
+    /// does not parse and anything else a 404.
+    async fn mock_server() -> String {
+        use std::collections::HashSet;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let seen = Arc::new(std::sync::Mutex::new(HashSet::new()));
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let seen = Arc::clone(&seen);
+                tokio::spawn(async move {
+                    let mut buf = vec![0u8; 4096];
+                    let n = socket.read(&mut buf).await.unwrap();
+                    let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                    let id = request.split_whitespace().nth(1).unwrap_or("").trim_start_matches("/?");
+                    let first = seen.lock().unwrap().insert(id.to_string());
+                    let (status, body) = if id.starts_with("ok") || (id.starts_with("flaky") && !first) {
+                        ("200 OK", LOG)
+                    } else if id.starts_with("flaky") {
+                        ("503 Service Unavailable", "")
+                    } else if id.starts_with("html") {
+                        ("200 OK", "<html></html>")
+                    } else if id.starts_with("broken") {
+                        ("200 OK", "<mjloggm ver=\"2.3\"><GO type=\"169\"/><BROKEN/></mjloggm>")
+                    } else {
+                        ("404 Not Found", "")
+                    };
+                    let response = format!(
+                        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status,
+                        body.len(),
+                        body
+                    );
+                    socket.write_all(response.as_bytes()).await.unwrap();
+                });
+            }
+        });
+        format!("http://{}/?", addr)
+    }
+
+    #[tokio::test]
+    async fn mock_endpoint() {
+        let config = DownloaderConfig {
+            base_url: mock_server().await,
+            concurrency: 2,
+            requests_per_second: Some(100.0),
+            retry_delay: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let save_dir = std::env::temp_dir().join("tenhou-logs-downloader-mock-endpoint");
+        let _ = std::fs::remove_dir_all(&save_dir);
+        let ids = ["ok1", "flaky1", "html1", "missing1", "broken1"].map(String::from).to_vec();
+
+        let report = download_files_with(ids.clone(), &save_dir, &config, |_| {}, &AtomicBool::new(false))
+            .await
+            .unwrap();
+        let statuses = report.files.iter().map(|f| f.status.clone()).collect::<Vec<_>>();
+        assert_eq!(statuses[0], FileStatus::Downloaded);
+        assert_eq!(statuses[1], FileStatus::Downloaded);
+        assert!(matches!(&statuses[2], FileStatus::Failed(r) if r.contains("InvalidContent")));
+        assert!(matches!(&statuses[3], FileStatus::Failed(r) if r.contains("404")));
+        assert!(matches!(&statuses[4], FileStatus::Failed(r) if r.contains("ParseError")));
+        assert_eq!(std::fs::read_to_string(save_dir.join("ok1.xml")).unwrap(), LOG);
+        assert!(!save_dir.join("html1.xml").exists());
+        assert!(!save_dir.join("missing1.xml.part").exists());
+
+        let report = download_files_with(ids, &save_dir, &config, |_| {}, &AtomicBool::new(true))
+            .await
+            .unwrap();
+        assert_eq!(report.cancelled, 5);
+
+        let config = DownloaderConfig { compress: true, ..config };
+        let ids = ["ok1", "ok2"].map(String::from).to_vec();
+        let report = download_files_with(ids, &save_dir, &config, |_| {}, &AtomicBool::new(false))
+            .await
+            .unwrap();
+        assert_eq!((report.skipped, report.downloaded), (1, 1));
+        let compressed = offline::read_maybe_gzip(save_dir.join("ok2.xml.gz")).unwrap();
+        assert_eq!(compressed, LOG.as_bytes());
+        std::fs::remove_dir_all(&save_dir).unwrap();
+    }
+
+    #[test]
+    fn validates_content() {
+        assert!(validate(LOG.as_bytes()).is_ok());
+        assert!(matches!(validate(b"<html></html>"), Err(DownloadError::InvalidContent)));
+        assert!(is_mjlog(b"<mjloggm ver=\"2.3\"><SHUFFLE/></mjloggm>"));
+        assert!(is_mjlog(b"\r\n<mjloggm ver=\"2.3\">"));
+        assert!(!is_mjlog(b"<html><body>not found</body></html>"));
+        assert!(!is_mjlog(b""));
+        assert!(DownloadError::Status(StatusCode::SERVICE_UNAVAILABLE).is_retryable());
+        assert!(!DownloadError::Status(StatusCode::NOT_FOUND).is_retryable());
+        assert!(!DownloadError::InvalidContent.is_retryable());
     }
 }
@@ -0,0 +1,109 @@
+//! Finding log ids in whatever the user pastes: viewer urls (`/0/?log=`, `/6/?log=`),
+//! download urls (`/0/log/?`), text files, CSV exports or clipboard dumps.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tenhou_parser::LOG_ID_PATTERN;
+
+use crate::is_saved;
+
+/// A log id, with the seat of the player who shared it when the link had `&tw=`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogRef {
+    pub id: String,
+    pub seat: Option<u8>,
+}
+
+lazy_static! {
+    // `tw` may follow the id after other url parameters, but not across whitespace or quotes.
+    static ref LOG_REF: Regex =
+        Regex::new(&format!(r#"({})(?:[^\s"'<>,;|]*?[&?]tw=([0-3]))?"#, LOG_ID_PATTERN)).unwrap();
+}
+
+/// Every distinct log id in `text`, in order of first appearance.
+/// A seat found on any occurrence of an id is kept.
+pub fn extract_log_refs(text: &str) -> Vec<LogRef> {
+    let mut refs: Vec<LogRef> = vec![];
+    let mut index = HashMap::new();
+    for c in LOG_REF.captures_iter(text) {
+        let id = c[1].to_string();
+        let seat = c.get(2).and_then(|m| m.as_str().parse().ok());
+        match index.get(&id) {
+            Some(&i) => {
+                let known: &mut LogRef = &mut refs[i];
+                known.seat = known.seat.or(seat);
+            }
+            None => {
+                index.insert(id.clone(), refs.len());
+                refs.push(LogRef { id, seat });
+            }
+        }
+    }
+    refs
+}
+
+/// The first log id in `s`, e.g. from `2024072317gm-0089-0000-87c78500&tw=0.mjlog`.
+pub fn find_log_id(s: &str) -> Option<String> {
+    extract_log_refs(s).into_iter().next().map(|r| r.id)
+}
+
+/// The `(id, seat)` of the refs whose seat is known.
+pub fn owner_seats(refs: &[LogRef]) -> Vec<(String, u8)> {
+    refs.iter()
+        .filter_map(|r| Some((r.id.clone(), r.seat?)))
+        .collect()
+}
+
+/// Splits `refs` into the ones saved in none of `save_dirs` yet and the ones already saved.
+pub fn without_saved(refs: Vec<LogRef>, save_dirs: &[&Path]) -> (Vec<LogRef>, Vec<LogRef>) {
+    refs.into_iter()
+        .partition(|r| !save_dirs.iter().any(|dir| is_saved(dir, &r.id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links() {
+        let text = "\
+            https://tenhou.net/0/?log=2024072317gm-0089-0000-87c78500&tw=2\n\
+            see https://tenhou.net/6/?log=2024072217gm-0089-0000-d5b489ec&ts=3&tw=1 again\n\
+            http://tenhou.net/0/log/?2024072017gm-00a9-0000-1fdd9250\n\
+            id,date\n2024071919gm-0009-0000-adcf8a34,2024-07-19\n\
+            \"2024072317gm-0089-0000-87c78500\" 2024071919gm-0009-0000-adcf8a34 tw=3";
+        let refs = extract_log_refs(text);
+        let pairs = refs
+            .iter()
+            .map(|r| (r.id.as_str(), r.seat))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            pairs,
+            [
+                ("2024072317gm-0089-0000-87c78500", Some(2)),
+                ("2024072217gm-0089-0000-d5b489ec", Some(1)),
+                ("2024072017gm-00a9-0000-1fdd9250", None),
+                ("2024071919gm-0009-0000-adcf8a34", None),
+            ]
+        );
+        assert_eq!(
+            find_log_id("2024072317gm-0089-0000-87c78500&tw=0.mjlog").as_deref(),
+            Some("2024072317gm-0089-0000-87c78500")
+        );
+
+        let dir = std::env::temp_dir().join("tenhou-logs-downloader-links");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::create_dir_all(dir.join("library")).unwrap();
+        std::fs::write(dir.join("2024072217gm-0089-0000-d5b489ec.xml.gz"), "x").unwrap();
+        std::fs::write(dir.join("library/2024072017gm-00a9-0000-1fdd9250.xml"), "x").unwrap();
+        let (new, saved) = without_saved(refs.clone(), &[&dir]);
+        assert_eq!((new.len(), saved[0].seat), (3, Some(1)));
+        let (new, saved) = without_saved(refs, &[&dir, &dir.join("library")]);
+        assert_eq!((new.len(), saved.len()), (2, 2));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
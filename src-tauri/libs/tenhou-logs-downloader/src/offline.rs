@@ -0,0 +1,190 @@
+//! Importing logs without the network: gzip compressed `.mjlog` files saved by the
+//! Tenhou client, and the `sccYYYYMMDDHH.html(.gz)` game indexes of the Tenhou archive.
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use lazy_static::lazy_static;
+use regex::Regex;
+use tenhou_parser::event_emitter::GZIP_MAGIC;
+
+use crate::links::find_log_id;
+use crate::{
+    gzip, is_saved, validate, write_atomic, DownloadReport, DownloaderConfig, FileResult,
+    FileStatus,
+};
+
+/// Reads a file, decompressing it if it is gzip.
+pub fn read_maybe_gzip(path: impl AsRef<Path>) -> std::io::Result<Vec<u8>> {
+    let raw = std::fs::read(path)?;
+    if !raw.starts_with(&GZIP_MAGIC) {
+        return Ok(raw);
+    }
+    let mut content = vec![];
+    GzDecoder::new(raw.as_slice()).read_to_end(&mut content)?;
+    Ok(content)
+}
+
+/// Copies local (possibly gzip compressed) logs into `save_dir` as `<id>.xml`,
+/// or as `<id>.xml.gz` when `compress` is set.
+/// The id is taken from the file name; files already in `save_dir` are skipped.
+pub fn import_files(
+    paths: &[PathBuf],
+    save_dir: &Path,
+    compress: bool,
+) -> Result<DownloadReport, String> {
+    std::fs::create_dir_all(save_dir).map_err(|e| e.to_string())?;
+    let config = DownloaderConfig {
+        compress,
+        ..Default::default()
+    };
+    let files = paths
+        .iter()
+        .map(|path| {
+            let source = path.to_string_lossy().to_string();
+            let id = path
+                .file_name()
+                .and_then(|name| find_log_id(&name.to_string_lossy()))
+                .unwrap_or_default();
+            let target = save_dir.join(config.file_name(&id));
+            let status = match import_file(path, &id, &target, compress) {
+                Ok(true) => FileStatus::Downloaded,
+                Ok(false) => FileStatus::Skipped,
+                Err(e) => FileStatus::Failed(e),
+            };
+            FileResult {
+                id,
+                url: source,
+                path: target,
+                status,
+            }
+        })
+        .collect();
+    Ok(DownloadReport::new(files))
+}
+
+fn import_file(path: &Path, id: &str, target: &Path, compress: bool) -> Result<bool, String> {
+    if id.is_empty() {
+        return Err("No log id in the file name".to_string());
+    }
+    if target.parent().is_some_and(|dir| is_saved(dir, id)) {
+        return Ok(false);
+    }
+    let content = read_maybe_gzip(path).map_err(|e| e.to_string())?;
+    validate(&content).map_err(|e| e.to_string())?;
+    let content = if compress {
+        gzip(&content).map_err(|e| e.to_string())?
+    } else {
+        content
+    };
+    write_atomic(target, &content).map_err(|e| e.to_string())?;
+    Ok(true)
+}
+
+/// One game listed in a `scc*.html` index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub id: String,
+    pub players: Vec<String>,
+}
+
+lazy_static! {
+    static ref INDEX_PLAYER: Regex = Regex::new(r"(\S+?)\([+-]?\d").unwrap();
+}
+
+/// Parses the lines of a `scc*.html` index, which look like
+/// `00:08 | 07 | 四鳳東喰赤－ | <a href="http://tenhou.net/0/?log=...">牌譜</a> | A(+52.0) B(+5.0) C(-18.0) D(-39.0)<br>`.
+pub fn parse_index(content: &str) -> Vec<IndexEntry> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let id = find_log_id(line)?;
+            let names = line.rsplit('|').next().unwrap_or("").trim_end_matches("<br>");
+            let players = INDEX_PLAYER
+                .captures_iter(names)
+                .map(|c| c[1].to_string())
+                .collect();
+            Some(IndexEntry { id, players })
+        })
+        .collect()
+}
+
+/// Ids of the games `player` took part in, read from a `scc*.html` or `scc*.html.gz` index.
+pub fn index_ids(path: impl AsRef<Path>, player: &str) -> std::io::Result<Vec<String>> {
+    let content = read_maybe_gzip(path)?;
+    Ok(parse_index(&String::from_utf8_lossy(&content))
+        .into_iter()
+        .filter(|entry| entry.players.iter().any(|p| p == player))
+        .map(|entry| entry.id)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use super::*;
+
+    const INDEX: &str = "00:08 | 07 | 四鳳東喰赤－ | <a href=\"http://tenhou.net/0/?log=2024072300gm-00a9-0000-4c2fbc41\">牌譜</a> | A(+52.0) B(+5.0) C(-18.0) D(-39.0)<br>\r\n\
+        00:10 | 11 | 四鳳南喰赤－ | <a href=\"http://tenhou.net/0/?log=2024072300gm-00a9-0000-0f0e9d1b\">牌譜</a> | E(+60.0) A(+10.0) F(-20.0) G(-50.0)<br>\r\n\
+        00:12 | 09 | 四鳳南喰赤－ | <a href=\"http://tenhou.net/0/?log=2024072300gm-00a9-0000-aaaaaaaa\">牌譜</a> | H(+60.0) I(+10.0) J(-20.0) K(-50.0)<br>\r\n";
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn index() {
+        let entries = parse_index(INDEX);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].players, ["A", "B", "C", "D"]);
+
+        let dir = std::env::temp_dir().join("tenhou-logs-downloader-index");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("scc2024072300.html.gz");
+        std::fs::write(&path, gzip(INDEX.as_bytes())).unwrap();
+        assert_eq!(
+            index_ids(&path, "A").unwrap(),
+            ["2024072300gm-00a9-0000-4c2fbc41", "2024072300gm-00a9-0000-0f0e9d1b"]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn mjlog_files() {
+        let dir = std::env::temp_dir().join("tenhou-logs-downloader-mjlog-files");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = "<mjloggm ver=\"2.3\"></mjloggm>";
+        let files = [
+            dir.join("2024072317gm-0089-0000-87c78500&tw=0.mjlog"),
+            dir.join("2024072318gm-0089-0000-87c78500.xml"),
+            dir.join("2024072319gm-0089-0000-87c78500.mjlog"),
+            dir.join("notes.txt"),
+        ];
+        std::fs::write(&files[0], gzip(log.as_bytes())).unwrap();
+        std::fs::write(&files[1], log).unwrap();
+        std::fs::write(&files[2], gzip(b"<html></html>")).unwrap();
+        std::fs::write(&files[3], log).unwrap();
+
+        let save_dir = dir.join("logs");
+        let report = import_files(&files, &save_dir, false).unwrap();
+        assert_eq!((report.downloaded, report.failed), (2, 2));
+        assert_eq!(
+            std::fs::read_to_string(save_dir.join("2024072317gm-0089-0000-87c78500.xml")).unwrap(),
+            log
+        );
+        assert_eq!(import_files(&files[..1], &save_dir, true).unwrap().skipped, 1);
+        let report = import_files(&files[1..2], &dir.join("compressed"), true).unwrap();
+        assert_eq!(report.downloaded, 1);
+        let saved = dir.join("compressed/2024072318gm-0089-0000-87c78500.xml.gz");
+        assert_eq!(read_maybe_gzip(saved).unwrap(), log.as_bytes());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use crate::maj_event::{MajEvent, ToMajEvent};
+use flate2::bufread::GzDecoder;
 use quick_xml::events::Event;
 use quick_xml::Reader;
 use std::fs::File;
@@ -11,56 +12,147 @@ pub struct EventEmitter<R: BufRead> {
     buf: Vec<u8>,
 }
 
-impl<'a, R: BufRead> Iterator for EventEmitter<R> {
+/// What to do after one xml event.
+enum Step {
+    Skip,
+    Emit(MajEvent),
+    End,
+}
+
+fn step(event: quick_xml::Result<Event>, position: u64) -> Step {
+    match event {
+        Err(e) => {
+            panic!("Error reading XML at position {}: {:?}", position, e);
+        }
+        Ok(Event::Eof) | Ok(Event::End(_)) => Step::End,
+        Ok(Event::Start(ref e)) => {
+            assert_eq!(e.name().as_ref(), b"mjloggm");
+            let ver = e
+                .attributes()
+                .find(|a| a.as_ref().unwrap().key.as_ref() == b"ver")
+                .unwrap()
+                .unwrap();
+            let ver = ver.value.as_ref();
+            if ver != b"2.3" {
+                panic!(
+                    "Unsupported mjlog version: {:?}. The only supported version is 2.3",
+                    ver
+                );
+            }
+            Step::Skip
+        }
+        Ok(Event::Empty(ref e)) => match e.to_maj_event() {
+            Some(MajEvent::Go { r#type }) if !r#type.applicable() => Step::End,
+            Some(ev) => Step::Emit(ev),
+            None => Step::Skip,
+        },
+        _ => Step::Skip,
+    }
+}
+
+impl<R: BufRead> EventEmitter<R> {
+    pub fn from_reader(reader: R) -> Self {
+        EventEmitter {
+            xml_reader: Reader::from_reader(reader),
+            buf: Vec::new(),
+        }
+    }
+}
+
+impl<'a> EventEmitter<&'a [u8]> {
+    /// Parses a plain (not gzip compressed) mjlog held in memory.
+    pub fn from_bytes(bytes: &'a [u8]) -> Self {
+        Self::from_reader(bytes)
+    }
+}
+
+impl<R: BufRead> Iterator for EventEmitter<R> {
     type Item = MajEvent;
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            match self.xml_reader.read_event_into(&mut self.buf) {
-                Err(e) => {
-                    panic!(
-                        "Error reading XML at position {}: {:?}",
-                        self.xml_reader.buffer_position(),
-                        e
-                    );
-                }
-                Ok(Event::Eof) | Ok(Event::End(_)) => return None,
-                Ok(Event::Start(ref e)) => {
-                    assert_eq!(e.name().as_ref(), b"mjloggm");
-                    let ver = e
-                        .attributes()
-                        .find(|a| a.as_ref().unwrap().key.as_ref() == b"ver")
-                        .unwrap()
-                        .unwrap();
-                    let ver = ver.value.as_ref();
-                    if ver != b"2.3" {
-                        panic!(
-                            "Unsupported mjlog version: {:?}. The only supported version is 2.3",
-                            ver
-                        );
-                    }
-                }
-                Ok(Event::Empty(ref e)) => {
-                    if let Some(ev) = e.to_maj_event() {
-                        if let MajEvent::Go { r#type } = ev {
-                            if !r#type.applicable() {
-                                return None;
-                            }
-                        }
-                        return Some(ev);
-                    }
-                }
-                _ => {}
+            let event = self.xml_reader.read_event_into(&mut self.buf);
+            match step(event, self.xml_reader.buffer_position()) {
+                Step::Skip => {}
+                Step::Emit(ev) => return Some(ev),
+                Step::End => return None,
             }
         }
     }
 }
 
-pub fn parse_file_iter(path: impl AsRef<Path>) -> EventEmitter<BufReader<File>> {
-    let xml_reader: Reader<BufReader<File>> = quick_xml::Reader::from_file(path).unwrap();
-    EventEmitter {
-        xml_reader,
-        buf: Vec::new(),
+/// [`EventEmitter`] over an async reader, e.g. a response body.
+#[cfg(feature = "async")]
+pub struct AsyncEventEmitter<R: tokio::io::AsyncBufRead + Unpin> {
+    xml_reader: Reader<R>,
+    buf: Vec<u8>,
+}
+
+#[cfg(feature = "async")]
+impl<R: tokio::io::AsyncBufRead + Unpin> AsyncEventEmitter<R> {
+    pub fn from_reader(reader: R) -> Self {
+        AsyncEventEmitter {
+            xml_reader: Reader::from_reader(reader),
+            buf: Vec::new(),
+        }
     }
+
+    pub async fn next_event(&mut self) -> Option<MajEvent> {
+        loop {
+            let event = self.xml_reader.read_event_into_async(&mut self.buf).await;
+            match step(event, self.xml_reader.buffer_position()) {
+                Step::Skip => {}
+                Step::Emit(ev) => return Some(ev),
+                Step::End => return None,
+            }
+        }
+    }
+
+    pub async fn collect_events(mut self) -> Vec<MajEvent> {
+        let mut events = vec![];
+        while let Some(event) = self.next_event().await {
+            events.push(event);
+        }
+        events
+    }
+}
+
+/// The first two bytes of a gzip stream.
+pub const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Parses a plain or gzip compressed mjlog, telling them apart by the gzip magic bytes.
+pub fn parse_file_iter(path: impl AsRef<Path>) -> EventEmitter<Box<dyn BufRead>> {
+    let mut file = BufReader::new(File::open(path).unwrap());
+    let reader: Box<dyn BufRead> = if file.fill_buf().unwrap().starts_with(&GZIP_MAGIC) {
+        Box::new(BufReader::new(GzDecoder::new(file)))
+    } else {
+        Box::new(file)
+    };
+    EventEmitter::from_reader(reader)
+}
+
+/// Runs `parse`, turning the panics raised on malformed logs into an error message.
+///
+/// The panic hook is left alone, so each malformed log still prints its panic message (and a
+/// backtrace when `RUST_BACKTRACE` is set) to stderr; swapping the hook is process wide and would
+/// hide panics from other threads too.
+fn catch_parse(parse: impl FnOnce() -> Vec<MajEvent>) -> Result<Vec<MajEvent>, String> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(parse)).map_err(|e| {
+        e.downcast_ref::<String>()
+            .cloned()
+            .or_else(|| e.downcast_ref::<&str>().map(|s| s.to_string()))
+            .unwrap_or_else(|| "Unknown error while parsing".to_string())
+    })
+}
+
+/// Parses a whole file, turning the panics raised on malformed logs into an error message.
+pub fn try_parse_file(path: impl AsRef<Path>) -> Result<Vec<MajEvent>, String> {
+    let path = path.as_ref();
+    catch_parse(|| parse_file_iter(path).collect())
+}
+
+/// Parses a whole mjlog held in memory, turning the panics raised on malformed logs into an error message.
+pub fn try_parse_bytes(bytes: &[u8]) -> Result<Vec<MajEvent>, String> {
+    catch_parse(|| EventEmitter::from_bytes(bytes).collect())
 }
 
 pub fn guess_user_id(path: impl AsRef<Path>) -> Option<String> {
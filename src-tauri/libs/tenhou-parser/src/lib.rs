@@ -2,4 +2,46 @@ pub mod maj_event;
 pub mod utils;
 pub mod event_emitter;
 
+/// Bump whenever a change to the parser alters the events produced for the same log,
+/// so that caches built from parsed logs know to rebuild.
+pub const PARSER_VERSION: u32 = 2;
 
+/// A log id such as `2024072300gm-00a9-0000-4c2fbc41`. The lobby and rule parts are hex.
+pub const LOG_ID_PATTERN: &str = r"\d{10}gm-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{8}";
+
+#[cfg(test)]
+mod tests {
+    use crate::event_emitter::{try_parse_bytes, EventEmitter};
+    use crate::maj_event::MajEvent;
+
+    const LOG: &str = r#"<mjloggm ver="2.3"><SHUFFLE seed="" ref=""/><GO type="169" lobby="0"/><UN n0="%41" n1="%42" n2="%43" n3="%44" dan="16,15,14,13" rate="2100.00,2000.00,1900.00,1800.00" sx="M,M,M,M"/><TAIKYOKU oya="0"/><INIT seed="0,0,0,2,3,52" ten="250,250,250,250" oya="0" hai0="0,4,8,12,16,20,24,28,32,36,40,44,48" hai1="1,5,9,13,17,21,25,29,33,37,41,45,49" hai2="2,6,10,14,18,22,26,30,34,38,42,46,50" hai3="3,7,11,15,19,23,27,31,35,39,43,47,51"/><T60/><D60/><U61/><E61/><AGARI ba="0,0" hai="0,4,8,12,16,20,24,28,32,36,40,44,48,61" machi="61" ten="30,12000,1" yaku="8,1,52,1" doraHai="52" who="0" fromWho="1" sc="250,120,250,-120,250,0,250,0" owari="370,47.0,130,-27.0,250,-5.0,250,-15.0"/></mjloggm>"#;
+
+    #[test]
+    fn from_bytes() {
+        let events = EventEmitter::from_bytes(LOG.as_bytes()).collect::<Vec<_>>();
+        assert!(matches!(&events[1], MajEvent::UN { id, .. } if id[0] == "A"));
+        assert!(matches!(events.last(), Some(MajEvent::Agari { actor: 0, fromwho: 1, .. })));
+        let south = LOG.replace("seed=\"0,", "seed=\"5,");
+        assert!(EventEmitter::from_bytes(south.as_bytes())
+            .any(|e| matches!(e, MajEvent::Init { round_wind: 1, kyoku: 2, .. })));
+        assert_eq!(
+            EventEmitter::from_reader(std::io::BufReader::new(LOG.as_bytes())).count(),
+            events.len()
+        );
+
+        assert_eq!(try_parse_bytes(LOG.as_bytes()).unwrap().len(), events.len());
+        assert!(try_parse_bytes(b"<html></html>").is_err());
+        // Sanma and other logs the analyser does not support end at GO.
+        let sanma = LOG.replace("type=\"169\"", "type=\"185\"");
+        assert!(try_parse_bytes(sanma.as_bytes()).unwrap().is_empty());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn from_async_reader() {
+        use crate::event_emitter::AsyncEventEmitter;
+
+        let events = AsyncEventEmitter::from_reader(LOG.as_bytes()).collect_events().await;
+        assert_eq!(events.len(), EventEmitter::from_bytes(LOG.as_bytes()).count());
+    }
+}
@@ -75,6 +75,9 @@ pub enum MajEvent {
     Init {
         dora_marker: Pai,
         honba: u8,
+        /// 场风, 0 为东场, 1 为南场, 2 为西场
+        round_wind: u8,
+        /// 局数, 1 到 4
         kyoku: u8,
         kyotaku: u8,
         oya: u8,
@@ -199,13 +202,7 @@ impl<'a> ToMajEvent for &BytesStart<'a> {
             "INIT" => {
                 let seed: Vec<u8> = e.get_attribute("seed").unwrap().into_num_vec();
                 let now_kyu: u8 = seed[0];
-                let _bakaze: &str = if now_kyu < 4 {
-                    "E"
-                } else if now_kyu < 8 {
-                    "S"
-                } else {
-                    "W"
-                };
+                let round_wind: u8 = now_kyu / 4;
                 let dora_marker: Pai = Pai::from(seed[5]);
                 let honba: u8 = seed[1];
                 let kyotaku: u8 = seed[2];
@@ -249,6 +246,7 @@ impl<'a> ToMajEvent for &BytesStart<'a> {
                 Some(MajEvent::Init {
                     dora_marker,
                     honba,
+                    round_wind,
                     kyoku,
                     kyotaku,
                     oya,
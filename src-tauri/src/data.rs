@@ -1,34 +1,161 @@
-use std::rc::Rc;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
 use maj_analyser::counter::Counter;
-use maj_analyser::game::Game;
-use tenhou_parser::event_emitter::parse_file_iter;
-
-#[tauri::command]
-pub fn parse_logs(id: String) -> Counter {
-    let path = std::env::current_dir().unwrap().join("logs");
-    let mut counters = Game::create_counters(vec![&id]);
-    let mut game = Game::new(&counters);
-    match path.read_dir() {
-        Ok(rd) => {
-            rd.for_each(|entry| {
-                let entry = entry.unwrap();
-                let path = entry.path();
-                let _path_str = path.to_str().unwrap().to_string();
-                if path.is_file() {
-                    parse_file_iter(path).for_each(|event| {
-                        game.on_event(event);
-                    });
-                }
-            });
-        }
-        Err(_) => {}
+use maj_store::kyokus::ProfileKyoku;
+use maj_store::manifest::ManifestEntry;
+use maj_store::opponents::OpponentIndex;
+use maj_store::profile::{PlayerProfile, ProfileStats};
+use maj_store::query::GameFilter;
+use maj_store::situations::SituationStats;
+use maj_store::LogStore;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::settings::{current_settings, Settings};
+use crate::task::Tasks;
+
+/// Serializes the commands using the game store, so that only one imports into it at a
+/// time and a `cancel_parse` only ever applies to the import that is running.
+#[derive(Default)]
+pub struct StoreLock(Mutex<()>);
+
+/// Opens the game store and imports any logs that are not in it yet,
+/// emitting `parse-progress` for every parsed file.
+/// Must be called with the [`StoreLock`] held.
+fn open_store(app: &AppHandle, settings: &Settings) -> Result<LogStore, String> {
+    let cancel = &app.state::<Tasks>().parse;
+    cancel.store(false, Ordering::Relaxed);
+    std::fs::create_dir_all(&settings.data_dir).map_err(|e| e.to_string())?;
+    let store = LogStore::open(settings.db_path()).map_err(|e| e.to_string())?;
+    let summary = store
+        .import_dirs_with(
+            &settings.roots(),
+            |p| {
+                let _ = app.emit("parse-progress", p);
+            },
+            cancel,
+        )
+        .map_err(|e| e.to_string())?;
+    if summary.cancelled {
+        return Err("Cancelled".to_string());
     }
+    Ok(store)
+}
+
+/// Remembers the seat the user played in for each `(id, seat)`, before the games are imported.
+pub async fn set_owner_seats(app: AppHandle, seats: Vec<(String, u8)>) -> Result<(), String> {
+    if seats.is_empty() {
+        return Ok(());
+    }
+    tauri::async_runtime::spawn_blocking(move || {
+        let lock = app.state::<StoreLock>();
+        let _guard = lock.0.lock().unwrap_or_else(|e| e.into_inner());
+        let settings = current_settings(&app);
+        std::fs::create_dir_all(&settings.data_dir).map_err(|e| e.to_string())?;
+        let store = LogStore::open(settings.db_path()).map_err(|e| e.to_string())?;
+        store.set_owner_seats(&seats).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Runs `f` on the up to date store, off the main thread so the window stays responsive
+/// and the import can be cancelled.
+pub async fn with_store<T: Send + 'static>(
+    app: AppHandle,
+    f: impl FnOnce(LogStore) -> Result<T, String> + Send + 'static,
+) -> Result<T, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let lock = app.state::<StoreLock>();
+        let _guard = lock.0.lock().unwrap_or_else(|e| e.into_inner());
+        let settings = current_settings(&app);
+        f(open_store(&app, &settings)?)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn parse_logs(app: AppHandle, id: String) -> Result<Counter, String> {
+    with_store(app, move |store| store.player_counter(&id).map_err(|e| e.to_string())).await
+}
+
+/// Statistics of the user over the games whose links had their seat, whatever their name.
+#[tauri::command]
+pub async fn parse_owner_logs(app: AppHandle) -> Result<Counter, String> {
+    with_store(app, |store| {
+        store
+            .owner_counter(&GameFilter::default())
+            .map_err(|e| e.to_string())
+    })
+    .await
+}
 
-    let rc = counters.remove(&id).unwrap();
-    Rc::unwrap_or_clone(rc).into_inner()
+/// The profile called `name` in the settings, or else a profile of just that player name.
+fn find_profile(settings: Settings, name: &str) -> PlayerProfile {
+    settings
+        .profiles
+        .into_iter()
+        .find(|p| p.name == name)
+        .unwrap_or_else(|| name.into())
 }
 
+/// Statistics of the profile called `name` in the settings, in total and per alias.
 #[tauri::command]
-pub fn guess_user_id() -> Option<String> {
-    tenhou_parser::event_emitter::guess_user_id(std::env::current_dir().unwrap().join("logs"))
-}
\ No newline at end of file
+pub async fn profile_stats(app: AppHandle, name: String) -> Result<ProfileStats, String> {
+    let profile = find_profile(current_settings(&app), &name);
+    with_store(app, move |store| {
+        store
+            .profile_stats(&profile, &GameFilter::default())
+            .map_err(|e| e.to_string())
+    })
+    .await
+}
+
+/// Results against every opponent of the profile or player `name`.
+#[tauri::command]
+pub async fn opponent_index(app: AppHandle, name: String) -> Result<OpponentIndex, String> {
+    let profile = find_profile(current_settings(&app), &name);
+    with_store(app, move |store| {
+        store
+            .opponent_index(&profile, &GameFilter::default())
+            .map_err(|e| e.to_string())
+    })
+    .await
+}
+
+/// Every round played by the profile or player `name`, most recent game first.
+#[tauri::command]
+pub async fn kyoku_records(app: AppHandle, name: String) -> Result<Vec<ProfileKyoku>, String> {
+    let profile = find_profile(current_settings(&app), &name);
+    with_store(app, move |store| {
+        store
+            .kyoku_records(&profile, &GameFilter::default())
+            .map_err(|e| e.to_string())
+    })
+    .await
+}
+
+/// Results of the profile or player `name` as dealer, leading, trailing and in the all-last.
+#[tauri::command]
+pub async fn situation_stats(app: AppHandle, name: String) -> Result<SituationStats, String> {
+    let profile = find_profile(current_settings(&app), &name);
+    with_store(app, move |store| {
+        store
+            .situation_stats(&profile, &GameFilter::default())
+            .map_err(|e| e.to_string())
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn guess_user_id(app: AppHandle) -> Option<String> {
+    with_store(app, |store| store.most_frequent_player().map_err(|e| e.to_string()))
+        .await
+        .ok()?
+}
+
+#[tauri::command]
+pub async fn list_failed_logs(app: AppHandle) -> Result<Vec<ManifestEntry>, String> {
+    with_store(app, |store| store.failed_files().map_err(|e| e.to_string())).await
+}
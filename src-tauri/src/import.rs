@@ -1,17 +1,114 @@
-use browser_ls_reader::read_all;
-use tenhou_logs_downloader::download_files;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use browser_ls_reader::{discover, read_profiles, FoundLog};
+use serde::Serialize;
+use tenhou_logs_downloader::links::{extract_log_refs, owner_seats, without_saved, LogRef};
+use tenhou_logs_downloader::offline;
+use tenhou_logs_downloader::{download_files_with, DownloadReport, DownloaderConfig};
+use tauri::{AppHandle, Emitter, Manager};
+use crate::data::{set_owner_seats, with_store};
+use crate::settings::current_settings;
+use crate::task::Tasks;
 
+/// Viewer links of the logs found in the browsers, one per line, with the seat (`tw`) when known.
 #[tauri::command]
 pub fn scan_local_logs() -> String {
-    read_all().unwrap().join("\n")
+    read_profiles(&discover())
+        .into_iter()
+        .map(|log| match log.entry.seat {
+            Some(seat) => format!("https://tenhou.net/0/?log={}&tw={}", log.id, seat),
+            None => format!("https://tenhou.net/0/?log={}", log.id),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
+/// The log ids found in the browsers, with the profile each came from.
 #[tauri::command]
-pub async fn download_logs(ids: String) -> ((u64, u64, u64), String) {
-    let urls = ids.split("\n").map(|s| "https://tenhou.net/0/log/?".to_string() + s).collect();
-    let save_dir = std::env::current_dir().unwrap().join("logs");
-    match download_files(urls, &save_dir).await {
-        Ok((skipped, downloaded, fail)) => ((skipped, downloaded, fail), "".to_string()),
-        Err(e) => ((0, 0, 0), e)
+pub fn scan_browser_profiles() -> Vec<FoundLog> {
+    read_profiles(&discover())
+}
+
+#[derive(Serialize)]
+pub struct ExtractedLogs {
+    /// 尚未下载的牌谱
+    new: Vec<LogRef>,
+    /// 已下载过的牌谱, 只用于记下链接中的座位
+    saved: Vec<LogRef>,
+}
+
+/// The log ids in pasted text (links, CSV, ...), split by whether they are downloaded already.
+#[tauri::command]
+pub fn extract_log_ids(app: AppHandle, text: String) -> ExtractedLogs {
+    let settings = current_settings(&app);
+    let (new, saved) = without_saved(extract_log_refs(&text), &settings.roots());
+    ExtractedLogs { new, saved }
+}
+
+/// The files handled by a download or local import, and how the import into the store went.
+#[derive(Serialize)]
+pub struct ImportReport {
+    #[serde(flatten)]
+    report: DownloadReport,
+    /// 保存后导入失败或被取消时的原因, 此时文件已经保存
+    parse_error: Option<String>,
+}
+
+impl ImportReport {
+    /// Imports the saved files; a failed or cancelled import does not lose the report.
+    async fn import(app: AppHandle, report: DownloadReport) -> Self {
+        let parse_error = with_store(app, |_| Ok(())).await.err();
+        ImportReport { report, parse_error }
     }
-}
\ No newline at end of file
+}
+
+/// Downloads the logs, emitting `download-progress` for every file, then imports them.
+/// The seats known from the links of both `logs` and the `saved` ones are remembered as the user's.
+#[tauri::command]
+pub async fn download_logs(
+    app: AppHandle,
+    logs: Vec<LogRef>,
+    saved: Vec<LogRef>,
+) -> Result<ImportReport, String> {
+    let settings = current_settings(&app);
+    let seats = owner_seats(&[logs.as_slice(), saved.as_slice()].concat());
+    set_owner_seats(app.clone(), seats).await?;
+    let ids = logs.into_iter().map(|r| r.id).collect();
+    let save_dir = settings.logs_dir.clone();
+    let tasks = app.state::<Tasks>();
+    tasks.download.store(false, Ordering::Relaxed);
+    let on_progress = |p| { let _ = app.emit("download-progress", p); };
+    let config = DownloaderConfig {
+        compress: settings.compress_logs,
+        ..Default::default()
+    };
+    let report = download_files_with(ids, &save_dir, &config, on_progress, &tasks.download).await?;
+    Ok(ImportReport::import(app.clone(), report).await)
+}
+
+/// Copies local `.mjlog` / `.xml` files into the logs folder, then imports them.
+#[tauri::command]
+pub async fn import_local_files(app: AppHandle, paths: Vec<PathBuf>) -> Result<ImportReport, String> {
+    let settings = current_settings(&app);
+    let names = paths
+        .iter()
+        .filter_map(|path| path.file_name())
+        .map(|name| name.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("\n");
+    set_owner_seats(app.clone(), owner_seats(&extract_log_refs(&names))).await?;
+    let report = tauri::async_runtime::spawn_blocking(move || {
+        offline::import_files(&paths, &settings.logs_dir, settings.compress_logs)
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    Ok(ImportReport::import(app, report).await)
+}
+
+/// Ids of `player`'s games in a `scc*.html(.gz)` index, one per line.
+#[tauri::command]
+pub fn read_log_index(path: PathBuf, player: String) -> Result<String, String> {
+    offline::index_ids(path, &player)
+        .map(|ids| ids.join("\n"))
+        .map_err(|e| e.to_string())
+}
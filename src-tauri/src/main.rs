@@ -1,21 +1,47 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use std::sync::Mutex;
+use tauri::Manager;
 use crate::import::{*};
 use crate::data::{*};
+use crate::settings::{*};
+use crate::task::{*};
 mod import;
 mod data;
+mod settings;
+mod task;
 // Learn more about Tauri commands at https://tauri.app/v1/guides/features/command
 
 fn main() {
     tauri::Builder::default()
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_shell::init())
+        .setup(|app| {
+            app.manage(Mutex::new(Settings::load(app.handle())));
+            app.manage(Tasks::default());
+            app.manage(StoreLock::default());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             scan_local_logs,
+            scan_browser_profiles,
+            extract_log_ids,
             download_logs,
+            import_local_files,
+            read_log_index,
             parse_logs,
+            parse_owner_logs,
+            profile_stats,
+            opponent_index,
+            kyoku_records,
+            situation_stats,
             guess_user_id,
+            list_failed_logs,
+            get_settings,
+            set_settings,
+            cancel_download,
+            cancel_parse,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,105 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use maj_store::profile::PlayerProfile;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+
+const SETTINGS_FILE: &str = "settings.json";
+
+/// The app settings, persisted as JSON in the platform data dir.
+///
+/// There is no shanten table cache location: the analyser finds shanten with a small search
+/// (see `maj_analyser::shanten`), and the table-based `shanten-calculator` that needed the
+/// cache is not part of the app, since it does not build against the current `pai` crate.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Settings {
+    /// Folder new downloads are saved to.
+    pub logs_dir: PathBuf,
+    /// Extra folders whose logs are analysed along with `logs_dir`.
+    pub library_roots: Vec<PathBuf>,
+    /// Save downloaded logs gzip compressed.
+    pub compress_logs: bool,
+    /// Players known under several names, whose games are counted together.
+    pub profiles: Vec<PlayerProfile>,
+    /// Platform data dir holding the settings file and the game database.
+    #[serde(skip_deserializing)]
+    pub data_dir: PathBuf,
+}
+
+impl Settings {
+    /// Loads the settings from the app data dir, falling back to defaults for anything missing.
+    pub fn load(app: &AppHandle) -> Self {
+        let data_dir = app
+            .path()
+            .app_data_dir()
+            .ok()
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_default();
+        let mut settings = std::fs::read_to_string(data_dir.join(SETTINGS_FILE))
+            .ok()
+            .and_then(|s| serde_json::from_str::<Settings>(&s).ok())
+            .unwrap_or_default();
+        settings.data_dir = data_dir;
+        settings.fill_defaults();
+        settings
+    }
+
+    fn fill_defaults(&mut self) {
+        if self.logs_dir.as_os_str().is_empty() {
+            // Older versions kept the logs next to the working directory.
+            self.logs_dir = std::env::current_dir()
+                .map(|dir| dir.join("logs"))
+                .ok()
+                .filter(|legacy| legacy.is_dir())
+                .unwrap_or_else(|| self.data_dir.join("logs"));
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        std::fs::create_dir_all(&self.data_dir).map_err(|e| e.to_string())?;
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(self.data_dir.join(SETTINGS_FILE), json).map_err(|e| e.to_string())
+    }
+
+    pub fn db_path(&self) -> PathBuf {
+        self.data_dir.join("games.db")
+    }
+
+    /// `logs_dir` followed by the library roots, without duplicates.
+    pub fn roots(&self) -> Vec<&Path> {
+        let mut roots: Vec<&Path> = vec![&self.logs_dir];
+        for root in self.library_roots.iter() {
+            if !roots.contains(&root.as_path()) {
+                roots.push(root);
+            }
+        }
+        roots
+    }
+}
+
+pub fn current_settings(app: &AppHandle) -> Settings {
+    app.state::<Mutex<Settings>>().lock().unwrap().clone()
+}
+
+#[tauri::command]
+pub fn get_settings(settings: State<'_, Mutex<Settings>>) -> Settings {
+    settings.lock().unwrap().clone()
+}
+
+#[tauri::command]
+pub fn set_settings(
+    settings: State<'_, Mutex<Settings>>,
+    new_settings: Settings,
+) -> Result<Settings, String> {
+    let mut settings = settings.lock().unwrap();
+    let mut new_settings = Settings {
+        data_dir: settings.data_dir.clone(),
+        ..new_settings
+    };
+    new_settings.fill_defaults();
+    new_settings.save()?;
+    *settings = new_settings;
+    Ok(settings.clone())
+}
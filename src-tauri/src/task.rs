@@ -0,0 +1,23 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tauri::State;
+
+/// Cancellation flags of the long running commands.
+/// A flag is cleared when its command starts and set by the matching `cancel_*` command.
+/// Imports run one at a time (see [`StoreLock`](crate::data::StoreLock)), so `parse`
+/// is only cleared once the import it cancelled is over.
+#[derive(Default)]
+pub struct Tasks {
+    pub download: AtomicBool,
+    pub parse: AtomicBool,
+}
+
+#[tauri::command]
+pub fn cancel_download(tasks: State<'_, Tasks>) {
+    tasks.download.store(true, Ordering::Relaxed);
+}
+
+#[tauri::command]
+pub fn cancel_parse(tasks: State<'_, Tasks>) {
+    tasks.parse.store(true, Ordering::Relaxed);
+}
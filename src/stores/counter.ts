@@ -158,10 +158,44 @@ export interface Counter {
   /// 各役种出现次数
   yakus: number[];
 
+  /// 放铳的详细汇总
+  deal_in: DealInProfile;
+  /// 面对立直时的押退
+  push_fold: PushFoldProfile;
+
   /// 总计R值
   tot_rate: number;
 }
 
+export interface DealInProfile {
+  /// 放铳次数
+  count: number;
+  /// 放铳总点数
+  total_score: number;
+  /// 放铳总巡目
+  total_junme: number;
+  /// 未听牌时放铳次数
+  not_tenpai: number;
+  /// 放铳给立直者次数
+  to_riichi: number;
+  /// 放铳给立直者且自己未听牌的次数
+  to_riichi_not_tenpai: number;
+  /// 放铳牌为现物的次数
+  genbutsu: number;
+  /// 放铳牌为筋的次数
+  suji: number;
+  /// 放铳牌为壁的次数
+  kabe: number;
+  /// 放铳牌为字牌的次数
+  honor: number;
+  /// 放铳牌为幺九数牌的次数
+  terminal: number;
+  /// 放铳牌为无筋无壁的中张牌的次数
+  unread_middle: number;
+  /// 手中有估计更安全的牌却放铳的次数
+  safer_in_hand: number;
+}
+
 export function computedCounters(c: Counter) {
   console.log(c);
 
@@ -294,3 +328,32 @@ export function computedCounters(c: Counter) {
 function percentify(value: number) {
   return (value * 100).toFixed(2) + "%";
 }
+
+export interface PushFoldProfile {
+  /// 有人立直且自己未立直时的打牌次数
+  turns: number;
+  /// 其中打出非现物的次数
+  pushes: number;
+  /// 打出筋牌的次数
+  push_suji: number;
+  /// 打出 No Chance 牌的次数
+  push_no_chance: number;
+  /// 打出 One Chance 牌的次数
+  push_one_chance: number;
+  /// 打出只可能单骑的字牌的次数
+  push_honor_tanki: number;
+  /// 打出还可能双碰的字牌的次数
+  push_honor_shanpon: number;
+  /// 打出其他危险牌的次数
+  push_other: number;
+  /// 打出宝牌附近危险牌的次数
+  push_dora_adjacent: number;
+  /// 听牌时押的次数
+  push_tenpai: number;
+  /// 打牌前两向听以上仍押的次数
+  push_two_shanten: number;
+  /// 可以听满贯的打牌次数
+  tenpai_mangan: number;
+  /// 其中拆掉听牌的次数
+  fold_tenpai_mangan: number;
+}